@@ -0,0 +1,118 @@
+//! Hashing selected fields of a [`Value`].
+//!
+//! Values are hashed in a canonical encoding: every value starts with a tag byte for its
+//! variant, strings are prefixed with their length and object entries are sorted by key. The
+//! checksum therefore does not depend on the iteration order of the underlying `HashMap`.
+
+use sha2::{Digest, Sha256};
+
+use crate::value::Value;
+
+impl Value {
+    /// SHA-256 over the values at the given JSON Pointers, see [`Value::pointer`].
+    ///
+    /// Only the listed fields contribute, so changes anywhere else keep the checksum the same.
+    /// A pointer that does not resolve is hashed as missing, which differs from `null`.
+    pub fn hash_subset(&self, pointers: &[&str]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for ptr in pointers {
+            update_str(&mut hasher, ptr);
+            match self.pointer(ptr) {
+                Some(value) => {
+                    hasher.update([1]);
+                    update_value(&mut hasher, value);
+                }
+                None => hasher.update([0]),
+            }
+        }
+        hasher.finalize().into()
+    }
+}
+
+fn update_str(hasher: &mut Sha256, x: &str) {
+    hasher.update((x.len() as u64).to_le_bytes());
+    hasher.update(x.as_bytes());
+}
+
+fn update_value(hasher: &mut Sha256, value: &Value) {
+    match value {
+        Value::String(x) => {
+            hasher.update([b's']);
+            update_str(hasher, x);
+        }
+        Value::Integer(x) => {
+            hasher.update([b'i']);
+            hasher.update(x.to_le_bytes());
+        }
+        Value::Number(x) => {
+            hasher.update([b'n']);
+            hasher.update(x.to_bits().to_le_bytes());
+        }
+        Value::Boolean(x) => hasher.update([b'b', *x as u8]),
+        Value::Array(values) => {
+            hasher.update([b'a']);
+            hasher.update((values.len() as u64).to_le_bytes());
+            for value in values {
+                update_value(hasher, value);
+            }
+        }
+        Value::Object(hash_map) => {
+            let mut entries: Vec<_> = hash_map.iter().collect();
+            entries.sort_by_key(|(k, _)| &***k);
+
+            hasher.update([b'o']);
+            hasher.update((entries.len() as u64).to_le_bytes());
+            for (key, value) in entries {
+                update_str(hasher, key);
+                update_value(hasher, value);
+            }
+        }
+        Value::Reference(x) => {
+            hasher.update([b'r']);
+            update_str(hasher, x);
+        }
+        Value::Null => hasher.update([b'z']),
+        Value::RawNumber(x) => {
+            hasher.update([b'x']);
+            update_str(hasher, x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn hash_subset_ignores_unlisted_fields() {
+        let mut value = Value::Object(HashMap::from_iter(vec![
+            ("id".into(), Value::Integer(1)),
+            ("name".into(), Value::String("a".to_string())),
+            ("updated_at".into(), Value::String("monday".to_string())),
+        ]));
+        let pointers = ["/id", "/name"];
+        let checksum = value.hash_subset(&pointers);
+
+        value.deep_merge(Value::Object(HashMap::from_iter(vec![(
+            "updated_at".into(),
+            Value::String("tuesday".to_string()),
+        )])));
+        assert_eq!(value.hash_subset(&pointers), checksum);
+
+        value.deep_merge(Value::Object(HashMap::from_iter(vec![(
+            "name".into(),
+            Value::String("b".to_string()),
+        )])));
+        assert_ne!(value.hash_subset(&pointers), checksum);
+    }
+
+    #[test]
+    fn hash_subset_missing_is_not_null() {
+        let value = Value::Object(HashMap::from_iter(vec![("a".into(), Value::Null)]));
+        let empty = Value::Object(HashMap::new());
+
+        assert_ne!(value.hash_subset(&["/a"]), empty.hash_subset(&["/a"]));
+    }
+}
@@ -0,0 +1,166 @@
+//! Reformatting JSON text at the token level.
+//!
+//! These functions never build a [`Value`](crate::value::Value), so key order and the exact
+//! spelling of numbers are kept as they are in the input. The text must still be a single
+//! value that parses with the default [`ParserConfig`], except that comments are allowed.
+//! Comments are dropped.
+
+use crate::{
+    lexer::{Lexer, TokenKind},
+    parser::{Error, Parser, ParserConfig},
+};
+
+/// Remove all whitespace between the tokens of `text`.
+pub fn minify(text: &str) -> Result<String, Error> {
+    validate(text)?;
+
+    Ok(Lexer::new(text)
+        .filter(|t| !t.is_whitespace() && t.kind != TokenKind::Comment)
+        .map(|t| t.data)
+        .collect())
+}
+
+/// Write `text` with every array element and object entry on its own line, indented by
+/// `indent` spaces per level. Empty arrays and objects stay on one line.
+pub fn prettify(text: &str, indent: usize) -> Result<String, Error> {
+    validate(text)?;
+    let tokens = Lexer::new(text).filter(|t| !t.is_whitespace() && t.kind != TokenKind::Comment);
+
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut depth = 0usize;
+    let mut tokens = tokens.peekable();
+    while let Some(token) = tokens.next() {
+        match token.kind {
+            TokenKind::StartMapping | TokenKind::StartArray => {
+                out.push_str(token.data);
+                let empty = tokens.next_if(|next| {
+                    matches!(
+                        (&token.kind, &next.kind),
+                        (TokenKind::StartMapping, TokenKind::EndMapping)
+                            | (TokenKind::StartArray, TokenKind::EndArray)
+                    )
+                });
+                match empty {
+                    Some(end) => out.push_str(end.data),
+                    None => {
+                        depth += 1;
+                        push_newline(&mut out, depth * indent);
+                    }
+                }
+            }
+            TokenKind::EndMapping | TokenKind::EndArray => {
+                depth = depth.saturating_sub(1);
+                push_newline(&mut out, depth * indent);
+                out.push_str(token.data);
+            }
+            TokenKind::Separator => {
+                out.push_str(token.data);
+                push_newline(&mut out, depth * indent);
+            }
+            TokenKind::KeySeparator => {
+                out.push_str(token.data);
+                out.push(' ');
+            }
+            _ => out.push_str(token.data),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Parse `text` first, so tokens that only whitespace kept apart, like the numbers in
+/// `[1 2]`, are an error instead of being joined.
+fn validate(text: &str) -> Result<(), Error> {
+    let config = ParserConfig {
+        allow_comments: true,
+        ..Default::default()
+    };
+    Parser::from_str(text).with_config(config).to_value()?;
+    Ok(())
+}
+
+fn push_newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.extend(std::iter::repeat_n(' ', indent));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Span, parser::ErrorKind};
+
+    #[test]
+    fn minify_pretty_document() {
+        let text =
+            "{\n  \"z\": 1.50,\n  \"a\": [\n    1e5,\n\t\"x y\"\n  ],\n  \"m\": {\"b\": true}\n}\n";
+
+        assert_eq!(
+            minify(text),
+            Ok(r#"{"z":1.50,"a":[1e5,"x y"],"m":{"b":true}}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn prettify_minified_document() {
+        let text = r#"{"z":1.50,"a":[1e5,"x y",[],{}],"m":{"b":true}}"#;
+        let expected = r#"{
+  "z": 1.50,
+  "a": [
+    1e5,
+    "x y",
+    [],
+    {}
+  ],
+  "m": {
+    "b": true
+  }
+}"#;
+
+        assert_eq!(prettify(text, 2), Ok(expected.to_string()));
+        assert_eq!(minify(expected), Ok(text.to_string()));
+    }
+
+    #[test]
+    fn prettify_scalar() {
+        assert_eq!(prettify(" 12 ", 4), Ok("12".to_string()));
+        assert_eq!(prettify("[1]", 0), Ok("[\n1\n]".to_string()));
+    }
+
+    #[test]
+    fn prettify_invalid_structure() {
+        assert_eq!(
+            prettify("]]]", 2),
+            Err(Error::invalid_token(Span::new(0, 1)))
+        );
+        assert!(prettify("[1, {\"a\": 2]", 2).is_err());
+        assert!(prettify("[1", 2).is_err());
+        assert_eq!(
+            prettify("[1.2.3]", 2),
+            Err(Error::with_span(ErrorKind::Lexer, Span::new(1, 5)))
+        );
+    }
+
+    #[test]
+    fn minify_drops_comments() {
+        assert_eq!(minify("[1, // one\n 2]"), Ok("[1,2]".to_string()));
+    }
+
+    #[test]
+    fn minify_lexer_error() {
+        assert_eq!(
+            minify("[1.2.3]"),
+            Err(Error::with_span(ErrorKind::Lexer, Span::new(1, 5)))
+        );
+        assert_eq!(
+            minify("[- 1]"),
+            Err(Error::with_span(ErrorKind::Lexer, Span::new(1, 2)))
+        );
+    }
+
+    #[test]
+    fn minify_invalid_structure() {
+        assert!(minify("[1 2 3]").is_err());
+        assert!(minify("\"a\" \"b\"").is_err());
+        assert_eq!(minify("[1, 2,\n 3]"), Ok("[1,2,3]".to_string()));
+    }
+}
@@ -1,7 +1,4 @@
-use std::{
-    iter::{Enumerate, Peekable},
-    str::Chars,
-};
+use std::{fmt, iter::Peekable, str::CharIndices};
 
 enum Either<T, S> {
     Left(T),
@@ -16,7 +13,8 @@ fn right<T, S>(s: S) -> Either<T, S> {
     Either::Right(s)
 }
 
-#[derive(Debug, PartialEq)]
+/// A byte range in the source text.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Span {
     start: usize,
     length: usize,
@@ -26,10 +24,28 @@ impl Span {
     pub fn as_range(&self) -> std::ops::Range<usize> {
         self.start..(self.start + self.length)
     }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Whether `offset` is inside the span, the end is exclusive so an empty span contains
+    /// nothing.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.as_range().contains(&offset)
+    }
 }
 
 impl Span {
-    fn new(start: usize, end: usize) -> Span {
+    pub(crate) fn new(start: usize, end: usize) -> Span {
         Span {
             start,
             length: end - start,
@@ -51,26 +67,56 @@ pub enum TokenKind {
     String,
     Integer,
     Boolean,
+    Null,
     Float,
     Reference,
+    /// A `//` comment up to the end of the line, without the line break, or a `/* */` comment.
+    /// Block comments do not nest, the first `*/` ends the comment.
+    Comment,
+    /// Malformed input, only emitted when the lexer is in recovery mode.
+    Error,
 }
 
 impl TokenKind {
     pub fn is_value(&self, include_reference: bool) -> bool {
         match self {
-            TokenKind::String | TokenKind::Integer | TokenKind::Boolean | TokenKind::Float => true,
+            TokenKind::String
+            | TokenKind::Integer
+            | TokenKind::Boolean
+            | TokenKind::Null
+            | TokenKind::Float => true,
             TokenKind::Reference => include_reference,
             _ => false,
         }
     }
     pub fn is_whitespace(&self) -> bool {
-        match self {
-            TokenKind::Spacing | TokenKind::TabSpacing | TokenKind::NewLine => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            TokenKind::Spacing | TokenKind::TabSpacing | TokenKind::NewLine
+        )
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
     }
 }
 
+/// A piece of the source text, as produced by iterating a [`Lexer`].
+///
+/// ```
+/// use json_outliner::lexer::{Lexer, TokenKind};
+///
+/// for token in Lexer::new(r#"{"a": 1}"#) {
+///     let color = match token.kind() {
+///         TokenKind::String => "green",
+///         TokenKind::Integer | TokenKind::Float => "blue",
+///         _ => "default",
+///     };
+///     println!("{:?} {} {}", token.span().as_range(), token.data(), color);
+/// }
+/// ```
 #[derive(Debug, PartialEq)]
 pub struct Token<'a> {
     pub kind: TokenKind,
@@ -79,6 +125,18 @@ pub struct Token<'a> {
 }
 
 impl<'a> Token<'a> {
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn data(&self) -> &'a str {
+        self.data
+    }
+
     pub fn is_value(&self, include_reference: bool) -> bool {
         self.kind.is_value(include_reference)
     }
@@ -91,49 +149,199 @@ impl<'a> Token<'a> {
 #[derive(Debug, Clone)]
 pub struct Lexer<'a> {
     pub text: &'a str,
-    chars: Peekable<Enumerate<Chars<'a>>>,
+    chars: Peekable<CharIndices<'a>>,
     pub position: usize,
     in_string: bool,
     string_escaped: bool,
     in_float: bool,
     in_number: bool,
     in_ref: bool,
+    recover: bool,
+    multiline_strings: bool,
+    non_finite: bool,
+    digit_separators: bool,
     pub is_error: bool,
+    error_span: Option<Span>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(text: &'a str) -> Self {
         Lexer {
             text,
-            chars: text.chars().enumerate().peekable(),
+            chars: text.char_indices().peekable(),
             position: 0,
             in_string: false,
             string_escaped: false,
             in_float: false,
             in_number: false,
             in_ref: false,
+            recover: false,
+            multiline_strings: false,
+            non_finite: false,
+            digit_separators: false,
             is_error: false,
+            error_span: None,
         }
     }
 
+    /// Start lexing at byte offset `start`, spans are still relative to the start of `text`.
+    ///
+    /// Returns `None` when `start` is not on a char boundary of `text`.
+    pub fn new_at(text: &'a str, start: usize) -> Option<Self> {
+        text.get(..start)?;
+        let mut lexer = Lexer::new(text);
+        while lexer.chars.next_if(|(idx, _)| *idx < start).is_some() {}
+        lexer.position = start;
+        Some(lexer)
+    }
+
+    /// Instead of stopping at malformed input, emit a [`TokenKind::Error`] token for it and
+    /// continue with the rest of the text.
+    pub fn with_recovery(mut self) -> Self {
+        self.recover = true;
+        self
+    }
+
+    /// Accept literal newlines inside strings, which standard JSON does not allow.
+    pub fn allow_multiline_strings(mut self) -> Self {
+        self.multiline_strings = true;
+        self
+    }
+
+    /// Read `Infinity` and `NaN` as [`TokenKind::Float`] instead of references, a `-` sign
+    /// before `Infinity` becomes part of the token.
+    pub fn allow_non_finite(mut self) -> Self {
+        self.non_finite = true;
+        self
+    }
+
+    /// Accept `_` between the digits of a number, like `1_000_000`. The separators stay in the
+    /// token data.
+    pub fn allow_digit_separators(mut self) -> Self {
+        self.digit_separators = true;
+        self
+    }
+
+    /// A table with the kind, span and data of every token, for debugging the lexer.
+    pub fn dump(self) -> String {
+        let rows: Vec<_> = self
+            .map(|t| {
+                (
+                    t.kind.to_string(),
+                    format!("{:?}", t.span.as_range()),
+                    t.data,
+                )
+            })
+            .collect();
+        let kind_width = rows
+            .iter()
+            .map(|(kind, _, _)| kind.len())
+            .max()
+            .unwrap_or(0);
+        let span_width = rows
+            .iter()
+            .map(|(_, span, _)| span.len())
+            .max()
+            .unwrap_or(0);
+
+        rows.iter()
+            .map(|(kind, span, data)| {
+                format!("{kind:<kind_width$}  {span:<span_width$}  {data:?}\n")
+            })
+            .collect()
+    }
+
+    /// Where the malformed input starts and ends, when lexing stopped because of an error.
+    pub fn error_span(&self) -> Option<Span> {
+        self.error_span
+    }
+
+    /// The zero based line and column of byte offset `offset`, with the column counted in
+    /// characters. A `\r\n` line ending counts as a single line break. An offset inside a
+    /// character is moved back to the start of that character.
+    ///
+    /// For many lookups on the same text a [`LineIndex`](crate::line_index::LineIndex) is
+    /// faster, it does not rescan the text for every offset.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = self.text.floor_char_boundary(offset);
+        let before = &self.text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let line = before.matches('\n').count();
+        let column = self.text[line_start..]
+            .char_indices()
+            .take_while(|(idx, _)| line_start + idx < offset)
+            .count();
+
+        (line, column)
+    }
+
+    /// How much of the input is covered by the tokens emitted so far.
+    pub fn bytes_consumed(&self) -> usize {
+        self.position
+    }
+
+    /// Span from the current position up to and including the byte at `current_index`, every
+    /// token except comments ends on an ASCII character so that character is a single byte.
     fn new_span(&self, current_index: usize) -> Span {
         Span::new(self.position, current_index + 1)
     }
 
-    fn new_token(&mut self, token_kind: TokenKind, current_index: usize) -> Token<'a> {
+    fn new_token(&mut self, token_kind: TokenKind, current_index: usize) -> (TokenKind, Span) {
         let span = self.new_span(current_index);
         self.new_token_from_span(token_kind, span)
     }
 
-    fn new_token_from_span(&mut self, token_kind: TokenKind, span: Span) -> Token<'a> {
+    fn new_token_from_span(&mut self, token_kind: TokenKind, span: Span) -> (TokenKind, Span) {
         self.reset_flags();
-        Token {
-            kind: token_kind,
-            data: &self.text[span.as_range()],
-            span,
+        (token_kind, span)
+    }
+
+    /// Iterate over the kinds and spans of the tokens, without slicing the text for every token.
+    pub fn spans(self) -> Spans<'a> {
+        Spans { lexer: self }
+    }
+
+    /// Iterate over the tokens, ending with an `Err` when lexing stops at malformed input
+    /// instead of ending as if the input was complete.
+    pub fn tokens_result(self) -> TokenResults<'a> {
+        TokenResults {
+            lexer: self,
+            reported: false,
         }
     }
 
+    /// When `keyword` starts at `idx` and is not the start of a longer identifier, consume it and
+    /// return the index of its last character.
+    fn consume_keyword(&mut self, idx: usize, keyword: &str) -> Option<usize> {
+        let mut ahead = self.chars.clone();
+        let mut end = idx;
+        for expected in keyword.chars().skip(1) {
+            (end, _) = ahead.next_if(|(_, x)| *x == expected)?;
+        }
+        if ahead.peek().is_some_and(|(_, x)| is_snakecase(*x)) {
+            return None;
+        }
+
+        self.chars = ahead;
+        Some(end)
+    }
+
+    /// Continue a reference with the character at `idx`, emitting the reference when it ends.
+    fn reference_char(&mut self, idx: usize) -> Option<(TokenKind, Span)> {
+        if self.chars.peek().is_some_and(|(_, c)| is_snakecase(*c)) {
+            self.in_ref = true;
+            None
+        } else {
+            Some(self.new_token(TokenKind::Reference, idx))
+        }
+    }
+
+    fn fail(&mut self, span: Span) -> Option<(TokenKind, Span)> {
+        self.is_error = true;
+        self.error_span = Some(span);
+        None
+    }
+
     fn reset_flags(&mut self) {
         self.in_float = false;
         self.in_number = false;
@@ -147,51 +355,155 @@ impl<'a> Iterator for Lexer<'a> {
     type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        self.next_span().map(|(kind, span)| Token {
+            kind,
+            data: &self.text[span.as_range()],
+            span,
+        })
+    }
+}
+
+/// Iterator over the `(TokenKind, Span)` pairs of a [`Lexer`], see [`Lexer::spans`].
+#[derive(Debug, Clone)]
+pub struct Spans<'a> {
+    lexer: Lexer<'a>,
+}
+
+impl<'a> Iterator for Spans<'a> {
+    type Item = (TokenKind, Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lexer.next_span()
+    }
+}
+
+/// Malformed input that made the lexer stop, see [`Lexer::tokens_result`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LexError {
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed token at {:?}", self.span.as_range())
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// Iterator over the tokens of a [`Lexer`] that reports errors, see [`Lexer::tokens_result`].
+#[derive(Debug, Clone)]
+pub struct TokenResults<'a> {
+    lexer: Lexer<'a>,
+    reported: bool,
+}
+
+impl<'a> Iterator for TokenResults<'a> {
+    type Item = Result<Token<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lexer.next() {
+            Some(token) => Some(Ok(token)),
+            None if self.reported => None,
+            None => {
+                self.reported = true;
+                self.lexer.error_span().map(|span| Err(LexError { span }))
+            }
+        }
+    }
+}
+
+impl<'a> Lexer<'a> {
+    fn next_span(&mut self) -> Option<(TokenKind, Span)> {
         if self.is_error {
             return None;
         }
 
         while let Some((idx, ch)) = self.chars.next() {
-            let mut item: Option<Either<_, (Token<'a>, usize)>> = None;
+            let mut item: Option<Either<_, ((TokenKind, Span), usize)>> = None;
 
             match ch {
-                '\\' if self.in_string == true => {
-                    self.string_escaped = true;
-                }
-                '"' if self.string_escaped == true => {
+                _ if self.string_escaped => {
                     self.string_escaped = false;
                 }
-                '"' if self.in_string == true => {
+                '\\' if self.in_string => {
+                    self.string_escaped = true;
+                }
+                '"' if self.in_string => {
                     self.in_string = false;
                     item = Some(left(self.new_token(TokenKind::String, idx)))
                 }
-                '"' if self.in_string == false => {
+                '"' if !self.in_string => {
                     self.in_string = true;
                 }
-                _ if self.in_string == true => {
+                '\n' if self.in_string && !self.multiline_strings => {
+                    let span = self.new_span(idx);
+                    if !self.recover {
+                        return self.fail(span);
+                    }
+                    item = Some(left(self.new_token_from_span(TokenKind::Error, span)))
+                }
+                _ if self.in_string => {
                     continue;
                 }
                 // x if "0123456789".contains(x) && self.in_number == false => {
                 //     self.in_number = true;
                 //     continue;
                 // }
-                '.' if self.in_float == true => {
-                    self.is_error = true;
-                    return None;
+                '.' if self.in_float && self.recover => {
+                    let mut end = idx;
+                    while let Some((next, _)) =
+                        self.chars.next_if(|(_, x)| "e-+.0123456789".contains(*x))
+                    {
+                        end = next;
+                    }
+                    item = Some(right((self.new_token(TokenKind::Error, end), end)))
                 }
-                '.' => {
+                '.' if self.in_float => {
+                    return self.fail(self.new_span(idx));
+                }
+                '.' if self
+                    .chars
+                    .peek()
+                    .is_some_and(|(_, x)| x.is_ascii_digit() || *x == 'e') =>
+                {
                     self.in_float = true;
                 }
-                // x if "0123456789".contains(x) && self.chars.peek().is_none() => {
-                //     let kind = if self.in_float {TokenKind::Float} else {TokenKind::Integer};
-                //     item = Some(left(self.new_token(kind, idx)))
-                // }
-                x if self.in_ref == false
+                // a trailing dot like `1.` ends the number, it is up to the parser to accept it
+                '.' if self.in_number => item = Some(left(self.new_token(TokenKind::Float, idx))),
+                '.' => {
+                    let span = self.new_span(idx);
+                    if !self.recover {
+                        return self.fail(span);
+                    }
+                    item = Some(left(self.new_token_from_span(TokenKind::Error, span)))
+                }
+                // hexadecimal integers, it is up to the parser to accept them
+                '0' if !self.in_ref
+                    && !self.in_number
+                    && !self.in_float
+                    && self.chars.next_if(|(_, x)| *x == 'x').is_some() =>
+                {
+                    let mut end = idx + 1;
+                    while let Some((next, _)) = self.chars.next_if(|(_, x)| x.is_ascii_hexdigit()) {
+                        end = next;
+                    }
+                    let kind = match (end > idx + 1, self.recover) {
+                        (true, _) => TokenKind::Integer,
+                        (false, true) => TokenKind::Error,
+                        (false, false) => return self.fail(self.new_span(end)),
+                    };
+                    item = Some(right((self.new_token(kind, end), end)))
+                }
+                // the number also ends at the end of the input, when there is nothing to peek at
+                x if !self.in_ref
                     && "0123456789".contains(x)
                     && self
                         .chars
                         .peek()
-                        .map(|(_, x)| !"e-.0123456789".contains(*x))
+                        .map(|(_, x)| {
+                            !("e-.0123456789".contains(*x) || self.digit_separators && *x == '_')
+                        })
                         .unwrap_or(true) =>
                 {
                     let kind = if self.in_float {
@@ -201,30 +513,79 @@ impl<'a> Iterator for Lexer<'a> {
                     };
                     item = Some(left(self.new_token(kind, idx)))
                 }
-                x if self.in_ref == false && "0123456789".contains(x) => {
+                x if !self.in_ref && "0123456789".contains(x) => {
                     self.in_number = true;
                 }
-                'e' if self.in_ref == false && self.in_number == true => {
-                    self.in_float = true;
+                // a digit separator needs a digit on both sides
+                '_' if !self.in_ref && self.in_number && self.digit_separators => {
+                    let between_digits = self.text.as_bytes()[idx - 1].is_ascii_digit()
+                        && self.chars.peek().is_some_and(|(_, x)| x.is_ascii_digit());
+                    if !between_digits {
+                        let span = self.new_span(idx);
+                        if !self.recover {
+                            return self.fail(span);
+                        }
+                        item = Some(left(self.new_token_from_span(TokenKind::Error, span)))
+                    }
                 }
-                't' if self.in_ref == false => {
-                    let span = self.new_span(self.position + 3);
-                    if let Some("true") = self.text.get(span.as_range()) {
-                        let (pos, _) = self.chars.nth(2).unwrap();
+                'e' if !self.in_ref && self.in_number => {
+                    self.in_float = true;
+
+                    // the exponent needs at least one digit, after an optional sign
+                    let mut ahead = self.chars.clone();
+                    let mut end = idx;
+                    if let Some((next, _)) = ahead.next_if(|(_, x)| *x == '-' || *x == '+') {
+                        end = next;
+                    }
+                    if !ahead.peek().is_some_and(|(_, x)| x.is_ascii_digit()) {
+                        if end > idx {
+                            self.chars.next();
+                        }
+                        let span = self.new_span(end);
+                        if !self.recover {
+                            return self.fail(span);
+                        }
                         item = Some(right((
-                            self.new_token_from_span(TokenKind::Boolean, span),
-                            pos,
-                        )));
+                            self.new_token_from_span(TokenKind::Error, span),
+                            end,
+                        )))
                     }
                 }
-                'f' if self.in_ref == false => {
-                    let span = self.new_span(self.position + 4);
-                    if let Some("false") = self.text.get(span.as_range()) {
-                        let (pos, _) = self.chars.nth(3).unwrap();
-                        item = Some(right((
-                            self.new_token_from_span(TokenKind::Boolean, span),
-                            pos,
-                        )));
+                't' if !self.in_ref => {
+                    item = match self.consume_keyword(idx, "true") {
+                        Some(end) => Some(right((self.new_token(TokenKind::Boolean, end), end))),
+                        None => self.reference_char(idx).map(left),
+                    }
+                }
+                'f' if !self.in_ref => {
+                    item = match self.consume_keyword(idx, "false") {
+                        Some(end) => Some(right((self.new_token(TokenKind::Boolean, end), end))),
+                        None => self.reference_char(idx).map(left),
+                    }
+                }
+                'n' if !self.in_ref => {
+                    item = match self.consume_keyword(idx, "null") {
+                        Some(end) => Some(right((self.new_token(TokenKind::Null, end), end))),
+                        None => self.reference_char(idx).map(left),
+                    }
+                }
+                'I' if !self.in_ref && self.non_finite => {
+                    item = match self.consume_keyword(idx, "Infinity") {
+                        Some(end) => Some(right((self.new_token(TokenKind::Float, end), end))),
+                        None => self.reference_char(idx).map(left),
+                    }
+                }
+                'N' if !self.in_ref && self.non_finite => {
+                    item = match self.consume_keyword(idx, "NaN") {
+                        Some(end) => Some(right((self.new_token(TokenKind::Float, end), end))),
+                        None => self.reference_char(idx).map(left),
+                    }
+                }
+                '$' if !self.in_ref => {
+                    if self.chars.peek().is_some_and(|(_, c)| is_snakecase(*c)) {
+                        self.in_ref = true;
+                    } else {
+                        item = Some(left(self.new_token(TokenKind::Reference, idx)))
                     }
                 }
                 x if is_snakecase(x)
@@ -239,6 +600,34 @@ impl<'a> Iterator for Lexer<'a> {
                 x if is_snakecase(x) => {
                     self.in_ref = true;
                 }
+                '/' if self.chars.next_if(|(_, x)| *x == '/').is_some() => {
+                    while self.chars.next_if(|(_, x)| *x != '\n').is_some() {}
+                    let end = self.chars.peek().map_or(self.text.len(), |(idx, _)| *idx);
+                    let token = self.new_token_from_span(TokenKind::Comment, Span::new(idx, end));
+                    self.position = end;
+                    return Some(token);
+                }
+                '/' if self.chars.next_if(|(_, x)| *x == '*').is_some() => {
+                    let mut closed = false;
+                    let mut prev = '/';
+                    for (_, x) in self.chars.by_ref() {
+                        if prev == '*' && x == '/' {
+                            closed = true;
+                            break;
+                        }
+                        prev = x;
+                    }
+                    let end = self.chars.peek().map_or(self.text.len(), |(idx, _)| *idx);
+                    let span = Span::new(idx, end);
+                    let kind = match (closed, self.recover) {
+                        (true, _) => TokenKind::Comment,
+                        (false, true) => TokenKind::Error,
+                        (false, false) => return self.fail(span),
+                    };
+                    let token = self.new_token_from_span(kind, span);
+                    self.position = end;
+                    return Some(token);
+                }
                 '[' => item = Some(left(self.new_token(TokenKind::StartArray, idx))),
                 ']' => item = Some(left(self.new_token(TokenKind::EndArray, idx))),
                 '{' => item = Some(left(self.new_token(TokenKind::StartMapping, idx))),
@@ -248,6 +637,18 @@ impl<'a> Iterator for Lexer<'a> {
                 ' ' => item = Some(left(self.new_token(TokenKind::Spacing, idx))),
                 '\t' => item = Some(left(self.new_token(TokenKind::TabSpacing, idx))),
                 '\n' => item = Some(left(self.new_token(TokenKind::NewLine, idx))),
+                // a sign is part of the number that follows it, a sign without a number is an error
+                '-' | '+'
+                    if self.chars.peek().is_some_and(|(_, x)| {
+                        x.is_ascii_digit() || *x == '.' || (self.non_finite && *x == 'I')
+                    }) => {}
+                '-' | '+' => {
+                    let span = self.new_span(idx);
+                    if !self.recover {
+                        return self.fail(span);
+                    }
+                    item = Some(left(self.new_token_from_span(TokenKind::Error, span)))
+                }
 
                 _ => {}
             }
@@ -265,6 +666,16 @@ impl<'a> Iterator for Lexer<'a> {
             }
         }
 
+        if self.in_string {
+            // the input ended before the closing quote
+            let span = Span::new(self.position, self.text.len());
+            if !self.recover {
+                return self.fail(span);
+            }
+            self.position = self.text.len();
+            return Some(self.new_token_from_span(TokenKind::Error, span));
+        }
+
         None
     }
 }
@@ -277,6 +688,24 @@ fn is_snakecase(c: char) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn span_accessors() {
+        let span = Span::new(2, 5);
+        assert_eq!(span.start(), 2);
+        assert_eq!(span.len(), 3);
+        assert!(!span.is_empty());
+        assert!(!span.contains(1));
+        assert!(span.contains(2));
+        assert!(span.contains(4));
+        assert!(!span.contains(5));
+
+        let empty = Span::new(3, 3);
+        assert_eq!(empty.start(), 3);
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+        assert!(!empty.contains(3));
+    }
+
     #[test]
     fn lexer_string() {
         let text = r#""data \"123\" ""#;
@@ -295,6 +724,188 @@ mod tests {
         )
     }
 
+    #[test]
+    fn lexer_multiline_string() {
+        let text = "[\"a\nb\"]";
+
+        let mut lexer = Lexer::new(text);
+        assert_eq!(lexer.next().map(|t| t.kind), Some(TokenKind::StartArray));
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.error_span(), Some(Span::new(1, 4)));
+
+        let tokens: Vec<_> = Lexer::new(text)
+            .allow_multiline_strings()
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::String, "\"a\nb\""),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_exponent_sign() {
+        let tokens: Vec<_> = Lexer::new("[1e+10,1.5e+3,-2e-2,+5]")
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Float, "1e+10"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Float, "1.5e+3"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Float, "-2e-2"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Integer, "+5"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+
+        let mut lexer = Lexer::new("1e+");
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.error_span(), Some(Span::new(0, 3)));
+    }
+
+    #[test]
+    fn lexer_bare_decimal_point() {
+        let tokens: Vec<_> = Lexer::new("[1.,.5,-.5]")
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Float, "1."),
+                (TokenKind::Separator, ","),
+                (TokenKind::Float, ".5"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Float, "-.5"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+
+        let mut lexer = Lexer::new("[.]");
+        assert_eq!(lexer.next().map(|t| t.kind), Some(TokenKind::StartArray));
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.error_span(), Some(Span::new(1, 2)));
+    }
+
+    #[test]
+    fn lexer_non_finite() {
+        let text = "[Infinity,-Infinity,NaN,Infinity_ref,NaNa]";
+        let tokens: Vec<_> = Lexer::new(text)
+            .allow_non_finite()
+            .filter(|t| t.kind != TokenKind::Separator)
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Float, "Infinity"),
+                (TokenKind::Float, "-Infinity"),
+                (TokenKind::Float, "NaN"),
+                (TokenKind::Reference, "Infinity_ref"),
+                (TokenKind::Reference, "NaNa"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+
+        let kinds: Vec<_> = Lexer::new("[NaN]").map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::StartArray,
+                TokenKind::Reference,
+                TokenKind::EndArray
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_digit_separators() {
+        let tokens: Vec<_> = Lexer::new("[1_000,1_000.5,-2_0e1_0]")
+            .allow_digit_separators()
+            .filter(|t| t.kind != TokenKind::Separator)
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Integer, "1_000"),
+                (TokenKind::Float, "1_000.5"),
+                (TokenKind::Float, "-2_0e1_0"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+
+        for (text, span) in [("1_ ", Span::new(0, 2)), ("1__0", Span::new(0, 2))] {
+            let mut lexer = Lexer::new(text).allow_digit_separators();
+            assert_eq!(lexer.next(), None);
+            assert_eq!(lexer.error_span(), Some(span));
+        }
+
+        let kinds: Vec<_> = Lexer::new("1_000").map(|t| t.kind).collect();
+        assert_eq!(kinds, vec![TokenKind::Integer, TokenKind::Reference]);
+    }
+
+    #[test]
+    fn lexer_sign_without_number() {
+        for (text, span) in [
+            ("+ 5", Span::new(0, 1)),
+            ("[+]", Span::new(1, 2)),
+            ("[- 1]", Span::new(1, 2)),
+            ("1e-", Span::new(0, 3)),
+        ] {
+            let mut lexer = Lexer::new(text);
+            while lexer.next().is_some() {}
+            assert_eq!(lexer.error_span(), Some(span), "{text}");
+        }
+
+        let kinds: Vec<_> = Lexer::new("[-]").with_recovery().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![TokenKind::StartArray, TokenKind::Error, TokenKind::EndArray]
+        );
+    }
+
+    #[test]
+    fn lexer_hex_integer() {
+        let tokens: Vec<_> = Lexer::new("[0xFF,0x0,07]")
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Integer, "0xFF"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Integer, "0x0"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Integer, "07"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+
+        let mut lexer = Lexer::new("[0x]");
+        assert_eq!(lexer.next().map(|t| t.kind), Some(TokenKind::StartArray));
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.error_span(), Some(Span::new(1, 3)));
+
+        let kinds: Vec<_> = Lexer::new("[0x]").with_recovery().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![TokenKind::StartArray, TokenKind::Error, TokenKind::EndArray]
+        );
+    }
+
     #[test]
     fn lexer_bool() {
         let text = "[true,false]";
@@ -648,6 +1259,313 @@ mod tests {
         );
     }
 
+    #[test]
+    fn lexer_recover_bad_number() {
+        let text = "[1.2.3, 4]";
+        let tokens: Vec<_> = Lexer::new(text)
+            .with_recovery()
+            .map(|t| (t.kind, t.data))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Error, "1.2.3"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Spacing, " "),
+                (TokenKind::Integer, "4"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+
+        let lexer = Lexer::new(text);
+        assert_eq!(lexer.clone().count(), 1);
+    }
+
+    #[test]
+    fn lexer_exponent_without_digits() {
+        let mut lexer = Lexer::new("1e");
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.error_span(), Some(Span::new(0, 2)));
+
+        let mut lexer = Lexer::new("[1e-]");
+        assert_eq!(lexer.next().map(|t| t.kind), Some(TokenKind::StartArray));
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.error_span(), Some(Span::new(1, 4)));
+
+        let tokens: Vec<_> = Lexer::new("[1e-, 1e5]")
+            .with_recovery()
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Error, "1e-"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Spacing, " "),
+                (TokenKind::Float, "1e5"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_sigil_reference() {
+        let tokens: Vec<_> = Lexer::new("[$my_ref, $]")
+            .map(|t| (t.kind, t.data))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Reference, "$my_ref"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Spacing, " "),
+                (TokenKind::Reference, "$"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_bytes_consumed() {
+        let mut lexer = Lexer::new(r#"{"a": 12, "b": true}"#);
+        assert_eq!(lexer.bytes_consumed(), 0);
+
+        let last = lexer.by_ref().take(5).last().unwrap();
+        assert_eq!(last.data, "12");
+        assert_eq!(lexer.bytes_consumed(), last.span.as_range().end);
+        assert_eq!(lexer.bytes_consumed(), 8);
+    }
+
+    #[test]
+    fn lexer_keyword_after_multibyte_char() {
+        let kinds: Vec<_> = Lexer::new(r#"["é",true]"#)
+            .spans()
+            .map(|(kind, _)| kind)
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::StartArray,
+                TokenKind::String,
+                TokenKind::Separator,
+                TokenKind::Boolean,
+                TokenKind::EndArray,
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_null() {
+        let tokens: Vec<_> = Lexer::new("[null,nullable, n]")
+            .map(|t| (t.kind, t.data))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Null, "null"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Reference, "nullable"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Spacing, " "),
+                (TokenKind::Reference, "n"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_multibyte_strings() {
+        let tokens: Vec<_> = Lexer::new(r#"["café", "🦀", x]"#)
+            .filter(|t| !t.is_whitespace())
+            .map(|t| (t.kind, t.data, t.span))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "[", Span::new(0, 1)),
+                (TokenKind::String, "\"café\"", Span::new(1, 8)),
+                (TokenKind::Separator, ",", Span::new(8, 9)),
+                (TokenKind::String, "\"🦀\"", Span::new(10, 16)),
+                (TokenKind::Separator, ",", Span::new(16, 17)),
+                (TokenKind::Reference, "x", Span::new(18, 19)),
+                (TokenKind::EndArray, "]", Span::new(19, 20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_tokens_result() {
+        let results: Vec<_> = Lexer::new("1.2.3").tokens_result().collect();
+        assert_eq!(
+            results,
+            vec![Err(LexError {
+                span: Span::new(0, 4)
+            })]
+        );
+
+        let tokens = Lexer::new("[1]")
+            .tokens_result()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(tokens.len(), 3);
+    }
+
+    #[test]
+    fn lexer_unterminated_string() {
+        let mut lexer = Lexer::new(r#"[1, "abc"#);
+        assert_eq!(lexer.by_ref().count(), 4);
+        assert!(lexer.is_error);
+        assert_eq!(lexer.error_span(), Some(Span::new(4, 8)));
+
+        let tokens: Vec<_> = Lexer::new(r#"[1, "abc"#)
+            .with_recovery()
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(tokens.last(), Some(&(TokenKind::Error, r#""abc"#)));
+    }
+
+    #[test]
+    fn lexer_line_col() {
+        let text = "{\r\n  \"é\": [\n    true\r\n  ]\n}";
+        let lexer = Lexer::new(text);
+
+        assert_eq!(lexer.line_col(0), (0, 0));
+        assert_eq!(lexer.line_col(1), (0, 1));
+        assert_eq!(lexer.line_col(3), (1, 0));
+        assert_eq!(lexer.line_col(text.find(':').unwrap()), (1, 5));
+        assert_eq!(lexer.line_col(text.find("true").unwrap()), (2, 4));
+        assert_eq!(lexer.line_col(text.find(']').unwrap()), (3, 2));
+        assert_eq!(lexer.line_col(text.len()), (4, 1));
+
+        let positions: Vec<_> = Lexer::new(text)
+            .filter(|t| t.kind == TokenKind::Boolean || t.kind == TokenKind::String)
+            .map(|t| lexer.line_col(t.span.start()))
+            .collect();
+        assert_eq!(positions, vec![(1, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn lexer_line_col_inside_char() {
+        let lexer = Lexer::new("é");
+        assert_eq!(lexer.line_col(1), (0, 0));
+        assert_eq!(lexer.line_col(2), (0, 1));
+
+        let lexer = Lexer::new("[\n\"🦀\"]");
+        assert_eq!(lexer.line_col(5), (1, 1));
+        assert_eq!(lexer.line_col(100), (1, 4));
+    }
+
+    #[test]
+    fn lexer_line_comment() {
+        let tokens: Vec<_> = Lexer::new("[1, // é \"x\"\n\"a // b\"]")
+            .map(|t| (t.kind, t.data))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Integer, "1"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Spacing, " "),
+                (TokenKind::Comment, "// é \"x\""),
+                (TokenKind::NewLine, "\n"),
+                (TokenKind::String, "\"a // b\""),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_block_comment() {
+        let tokens: Vec<_> = Lexer::new("[/* one */1,/* two\n * lines **/2]")
+            .map(|t| (t.kind, t.data))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, "["),
+                (TokenKind::Comment, "/* one */"),
+                (TokenKind::Integer, "1"),
+                (TokenKind::Separator, ","),
+                (TokenKind::Comment, "/* two\n * lines **/"),
+                (TokenKind::Integer, "2"),
+                (TokenKind::EndArray, "]"),
+            ]
+        );
+
+        let mut lexer = Lexer::new("[1 /*/ 2]");
+        assert_eq!(lexer.by_ref().count(), 3);
+        assert!(lexer.is_error);
+        assert_eq!(lexer.error_span(), Some(Span::new(3, 9)));
+    }
+
+    #[test]
+    fn lexer_keyword_prefixed_reference() {
+        for text in ["truey", "falsey", "nullable", "t", "f1"] {
+            let tokens: Vec<_> = Lexer::new(text).map(|t| (t.kind, t.data)).collect();
+
+            assert_eq!(tokens, vec![(TokenKind::Reference, text)]);
+        }
+    }
+
+    #[test]
+    fn lexer_new_at() {
+        let text = "abc [1]";
+        let tokens: Vec<_> = Lexer::new_at(text, 4)
+            .unwrap()
+            .map(|t| (t.kind, t.span))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::StartArray, Span::new(4, 5)),
+                (TokenKind::Integer, Span::new(5, 6)),
+                (TokenKind::EndArray, Span::new(6, 7)),
+            ]
+        );
+        assert!(Lexer::new_at(text, 8).is_none());
+
+        let mut lexer = Lexer::new_at("é [1]", 3).unwrap();
+        assert_eq!(lexer.next().map(|t| t.span), Some(Span::new(3, 4)));
+    }
+
+    #[test]
+    fn lexer_dump() {
+        let dump = Lexer::new(r#"{"a":1}"#).dump();
+
+        assert_eq!(
+            dump,
+            concat!(
+                "StartMapping  0..1  \"{\"\n",
+                "String        1..4  \"\\\"a\\\"\"\n",
+                "KeySeparator  4..5  \":\"\n",
+                "Integer       5..6  \"1\"\n",
+                "EndMapping    6..7  \"}\"\n",
+            )
+        );
+    }
+
+    #[test]
+    fn lexer_spans() {
+        let text = r#"{"a": [1.5, true, my_ref], "b": "c"}"#;
+        let expected: Vec<_> = Lexer::new(text).map(|t| (t.kind, t.span)).collect();
+        let spans: Vec<_> = Lexer::new(text).spans().collect();
+
+        assert_eq!(spans, expected);
+        assert_eq!(spans.len(), 20);
+    }
+
     #[test]
     fn lexer_reference_ending_in_boolean() {
         let text = r#"[my_reference_name_true, my_reference_name_false]"#;
@@ -1,3 +1,13 @@
+#[cfg(feature = "checksum")]
+mod checksum;
+pub mod format;
 pub mod lexer;
+pub mod line_index;
+mod macros;
 pub mod parser;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(feature = "serde_json")]
+mod serde_json_impl;
 pub mod value;
+pub mod writer;
@@ -0,0 +1,93 @@
+/// Maps byte offsets in a source text to zero based `(line, column)` pairs, with the column
+/// counted in characters like [`Lexer::line_col`](crate::lexer::Lexer::line_col).
+///
+/// The index is built once from the source, after that every lookup is a binary search over
+/// the line starts, so any [`Span`](crate::lexer::Span) can be resolved without lexing again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    /// The byte offsets of the characters that take more than one byte.
+    wide_chars: Vec<usize>,
+    /// For every wide character, how many bytes more than characters the text has up to and
+    /// including it.
+    extra_bytes: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+
+        let mut wide_chars = Vec::new();
+        let mut extra_bytes = Vec::new();
+        let mut extra = 0;
+        for (idx, ch) in text.char_indices().filter(|(_, ch)| !ch.is_ascii()) {
+            extra += ch.len_utf8() - 1;
+            wide_chars.push(idx);
+            extra_bytes.push(extra);
+        }
+
+        LineIndex {
+            line_starts,
+            wide_chars,
+            extra_bytes,
+        }
+    }
+
+    /// Returns the line and the column (in characters from the start of the line) of `offset`.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let start = self.line_starts[line];
+        let bytes = offset - start;
+        (
+            line,
+            bytes - (self.extra_before(offset) - self.extra_before(start)),
+        )
+    }
+
+    fn extra_before(&self, offset: usize) -> usize {
+        match self.wide_chars.partition_point(|idx| *idx < offset) {
+            0 => 0,
+            count => self.extra_bytes[count - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_multi_line() {
+        let text = "{\n  \"a\": 1,\n  \"b\": [\n    true\n  ]\n}";
+        let index = LineIndex::new(text);
+
+        assert_eq!(index.line_col(0), (0, 0));
+        assert_eq!(index.line_col(1), (0, 1));
+        assert_eq!(index.line_col(2), (1, 0));
+        assert_eq!(index.line_col(4), (1, 2));
+        assert_eq!(index.line_col(text.find("true").unwrap()), (3, 4));
+        assert_eq!(index.line_col(text.len() - 1), (5, 0));
+    }
+
+    #[test]
+    fn line_col_counts_chars() {
+        let text = "é\nab\r\n\"ü€\": x";
+        let index = LineIndex::new(text);
+        let lexer = crate::lexer::Lexer::new(text);
+
+        assert_eq!(index.line_col(2), (0, 1));
+        assert_eq!(index.line_col(text.find('x').unwrap()), (2, 6));
+        for (offset, _) in text.char_indices() {
+            assert_eq!(index.line_col(offset), lexer.line_col(offset), "{offset}");
+        }
+    }
+
+    #[test]
+    fn line_col_single_line() {
+        let index = LineIndex::new("[1, 2]");
+
+        assert_eq!(index.line_col(4), (0, 4));
+    }
+}
@@ -0,0 +1,139 @@
+/// Build a [`Value`](crate::value::Value) from JSON-like syntax.
+///
+/// Objects, arrays, `null` and literals are written as in JSON, with object keys as string
+/// literals. Anything else in value position is a Rust expression converted with
+/// [`Value::from`](crate::value::Value), so existing values and variables can be interpolated.
+/// Bare identifiers are therefore variables, not references; write a reference as
+/// `Value::Reference(name)`.
+///
+/// ```
+/// use json_outliner::{json, value::Value};
+///
+/// let name = "ada";
+/// let value = json!({"name": name, "tags": [1, -2.5, true, null], "parent": Value::Null});
+/// let parsed: Value = r#"{"name": "ada", "tags": [1, -2.5, true, null], "parent": null}"#
+///     .parse()
+///     .unwrap();
+/// assert_eq!(value, parsed);
+/// ```
+#[macro_export]
+macro_rules! json {
+    // Array elements are collected into `[$($elems,)*]` until no tokens are left.
+    (@array [$($elems:expr,)*]) => {
+        vec![$($elems,)*]
+    };
+    (@array [$($elems:expr,)*] , $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)*] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] null $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!(null),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] [$($array:tt)*] $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!([$($array)*]),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] {$($object:tt)*} $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!({$($object)*}),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $next:expr , $($rest:tt)*) => {
+        $crate::json!(@array [$($elems,)* $crate::json!($next),] $($rest)*)
+    };
+    (@array [$($elems:expr,)*] $last:expr) => {
+        $crate::json!(@array [$($elems,)* $crate::json!($last),])
+    };
+
+    // Object entries are inserted into `$object` one at a time.
+    (@object $object:ident) => {};
+    (@object $object:ident , $($rest:tt)*) => {
+        $crate::json!(@object $object $($rest)*)
+    };
+    (@object $object:ident $key:literal : null $($rest:tt)*) => {
+        $object.insert($crate::value::Key::from($key), $crate::json!(null));
+        $crate::json!(@object $object $($rest)*)
+    };
+    (@object $object:ident $key:literal : [$($array:tt)*] $($rest:tt)*) => {
+        $object.insert($crate::value::Key::from($key), $crate::json!([$($array)*]));
+        $crate::json!(@object $object $($rest)*)
+    };
+    (@object $object:ident $key:literal : {$($inner:tt)*} $($rest:tt)*) => {
+        $object.insert($crate::value::Key::from($key), $crate::json!({$($inner)*}));
+        $crate::json!(@object $object $($rest)*)
+    };
+    (@object $object:ident $key:literal : $value:expr , $($rest:tt)*) => {
+        $object.insert($crate::value::Key::from($key), $crate::json!($value));
+        $crate::json!(@object $object $($rest)*)
+    };
+    (@object $object:ident $key:literal : $value:expr) => {
+        $object.insert($crate::value::Key::from($key), $crate::json!($value));
+    };
+
+    (null) => {
+        $crate::value::Value::Null
+    };
+    ([$($array:tt)*]) => {
+        $crate::value::Value::Array($crate::json!(@array [] $($array)*))
+    };
+    ({$($inner:tt)*}) => {
+        $crate::value::Value::Object({
+            #[allow(unused_mut)]
+            let mut object = ::std::collections::HashMap::new();
+            $crate::json!(@object object $($inner)*);
+            object
+        })
+    };
+    ($other:expr) => {
+        $crate::value::Value::from($other)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::value::Value;
+
+    #[test]
+    fn nested() {
+        let tags = Value::Array(vec![Value::Reference("tag".to_string())]);
+        let count = 3;
+
+        let value = json!({
+            "name": "outline",
+            "count": count,
+            "ratio": -0.5,
+            "items": [1, [], {}, {"done": false, "note": null}, null, "x".repeat(2)],
+            "tags": tags.clone(),
+        });
+
+        let expected = Value::Object(HashMap::from_iter(vec![
+            ("name".into(), Value::String("outline".to_string())),
+            ("count".into(), Value::Integer(3)),
+            ("ratio".into(), Value::Number(-0.5)),
+            (
+                "items".into(),
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::Array(vec![]),
+                    Value::Object(HashMap::new()),
+                    Value::Object(HashMap::from_iter(vec![
+                        ("done".into(), Value::Boolean(false)),
+                        ("note".into(), Value::Null),
+                    ])),
+                    Value::Null,
+                    Value::String("xx".to_string()),
+                ]),
+            ),
+            ("tags".into(), tags),
+        ]));
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(json!(null), Value::Null);
+        assert_eq!(json!(true), Value::Boolean(true));
+        assert_eq!(json!(-7), Value::Integer(-7));
+        assert_eq!(json!(1.5), Value::Number(1.5));
+        assert_eq!(json!("a"), Value::String("a".to_string()));
+        assert_eq!(json!([null]), Value::Array(vec![Value::Null]));
+    }
+}
@@ -1,21 +1,39 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, fmt, num::IntErrorKind};
 
 use crate::{
-    lexer::{Lexer, Token, TokenKind},
-    value::ValueRef,
+    lexer::{Lexer, Span, Token, TokenKind},
+    value::{Value, ValueRef},
 };
 
 #[derive(Debug, PartialEq)]
 pub struct Error {
     kind: ErrorKind,
+    span: Option<Span>,
 }
 
 impl Error {
-    pub fn invalid_token() -> Error {
+    pub fn new(kind: ErrorKind) -> Error {
+        Error { kind, span: None }
+    }
+
+    pub fn with_span(kind: ErrorKind, span: Span) -> Error {
         Error {
-            kind: ErrorKind::InvalidToken,
+            kind,
+            span: Some(span),
         }
     }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn span(&self) -> Option<&Span> {
+        self.span.as_ref()
+    }
+
+    pub fn invalid_token(span: Span) -> Error {
+        Error::with_span(ErrorKind::InvalidToken, span)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -25,101 +43,592 @@ pub enum ErrorKind {
     InvalidInteger,
     InvalidBoolean,
     InvalidNumber,
+    InvalidEscape,
+    /// A `\u` escape that is not four hex digits, or a surrogate without its other half.
+    InvalidUnicodeEscape,
     DoubleSeparators,
+    DoubleKeySeparator,
+    TooManyElements,
+    TopLevelScalar,
+    EmptyReference,
+    InvalidOffset,
+    /// A bare identifier where a value was expected, only reported by a strict parser.
+    UnquotedIdentifier(String),
+    /// A key that appears twice in the same object, only reported when duplicate keys are
+    /// rejected.
+    DuplicateKey(String),
+    DepthLimitExceeded,
+    /// A number like `.5` or `1.` without digits on both sides of the decimal point.
+    BareDecimalPoint,
+    /// Anything but whitespace or comments after the value.
+    TrailingContent,
     None,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ErrorKind::Lexer => f.write_str("malformed token")?,
+            ErrorKind::InvalidToken => f.write_str("invalid token")?,
+            ErrorKind::InvalidInteger => f.write_str("invalid integer")?,
+            ErrorKind::InvalidBoolean => f.write_str("invalid boolean")?,
+            ErrorKind::InvalidNumber => f.write_str("invalid number")?,
+            ErrorKind::InvalidEscape => f.write_str("invalid escape sequence")?,
+            ErrorKind::InvalidUnicodeEscape => f.write_str("invalid unicode escape sequence")?,
+            ErrorKind::DoubleSeparators => f.write_str("double separators")?,
+            ErrorKind::DoubleKeySeparator => f.write_str("double key separator")?,
+            ErrorKind::TooManyElements => f.write_str("too many elements")?,
+            ErrorKind::TopLevelScalar => {
+                f.write_str("top level value is not an array or object")?
+            }
+            ErrorKind::EmptyReference => f.write_str("reference without a name")?,
+            ErrorKind::InvalidOffset => f.write_str("offset is not on a char boundary")?,
+            ErrorKind::UnquotedIdentifier(name) => write!(
+                f,
+                "unquoted identifier `{}`, did you mean \"{}\"?",
+                name, name
+            )?,
+            ErrorKind::DuplicateKey(key) => write!(f, "duplicate key `{}`", key)?,
+            ErrorKind::DepthLimitExceeded => f.write_str("too deeply nested")?,
+            ErrorKind::BareDecimalPoint => {
+                f.write_str("decimal point without digits on both sides")?
+            }
+            ErrorKind::TrailingContent => f.write_str("unexpected content after the value")?,
+            ErrorKind::None => f.write_str("no value")?,
+        }
+        match &self.span {
+            Some(span) => write!(f, " at {:?}", span.as_range()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Options for a [`Parser`], the defaults accept standard JSON and references.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParserConfig {
+    /// Skip `//` and `/* */` comments like whitespace, without this option a comment is an
+    /// invalid token.
+    pub allow_comments: bool,
+    /// Limit the number of elements a single array or object may contain.
+    pub max_elements: Option<usize>,
+    /// Only accept documents where the top level value is an array or object.
+    pub require_top_level_container: bool,
+    /// Reject bare identifiers like `foo` or `NaN` instead of reading them as references,
+    /// references can still be written with a `$` sigil.
+    pub strict: bool,
+    /// Reject objects that contain the same key twice, instead of keeping the last value.
+    pub reject_duplicate_keys: bool,
+    /// How many arrays and objects may be nested in each other, `128` by default.
+    pub max_depth: usize,
+    /// Accept hexadecimal integers like `0xFF00FF`, without this option they are invalid
+    /// integers.
+    pub allow_hex: bool,
+    /// Accept a leading `+` on numbers like `+5`, without this option they are invalid.
+    pub allow_plus_sign: bool,
+    /// Accept numbers like `007` or `01.5`, without this option a number may only start with
+    /// `0` when it is `0` itself or followed by a fraction.
+    pub allow_leading_zeros: bool,
+    /// Accept numbers like `.5` and `1.` that have no digits on one side of the decimal point.
+    pub allow_bare_decimal_point: bool,
+    /// Read `Infinity`, `-Infinity` and `NaN` as numbers, like JSON5, instead of as
+    /// references. A parsed `NaN` is not equal to anything, itself included, so values
+    /// containing it never compare equal.
+    pub allow_non_finite: bool,
+    /// Accept `_` between the digits of a number, like `1_000_000`.
+    pub allow_digit_separators: bool,
+    /// Keep numbers as [`ValueRef::RawNumber`] with their text from the source, so `123.4500`
+    /// or `1e10` is written back exactly as it was. Numbers are still checked like without
+    /// this option.
+    pub preserve_number_text: bool,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        ParserConfig {
+            allow_comments: false,
+            max_elements: None,
+            require_top_level_container: false,
+            strict: false,
+            reject_duplicate_keys: false,
+            max_depth: 128,
+            allow_hex: false,
+            allow_plus_sign: false,
+            allow_leading_zeros: false,
+            allow_bare_decimal_point: false,
+            allow_non_finite: false,
+            allow_digit_separators: false,
+            preserve_number_text: false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
+    config: ParserConfig,
+    #[cfg(feature = "rc_keys")]
+    interned_keys: Option<std::collections::HashSet<crate::value::Key>>,
 }
 
 impl<'a> Parser<'a> {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(text: &'a str) -> Self {
+        Parser::from_lexer(Lexer::new(text))
+    }
+
+    /// Parse the JSON that starts at byte offset `start` of `text`, for example a fragment
+    /// embedded in a larger document. Spans in errors are relative to the start of `text`.
+    pub fn from_str_at(text: &'a str, start: usize) -> Result<Self, Error> {
+        Lexer::new_at(text, start)
+            .map(Parser::from_lexer)
+            .ok_or(Error::new(ErrorKind::InvalidOffset))
+    }
+
+    pub fn from_lexer(lexer: Lexer<'a>) -> Self {
         Parser {
-            lexer: Lexer::new(text),
+            lexer,
+            config: ParserConfig::default(),
+            #[cfg(feature = "rc_keys")]
+            interned_keys: None,
         }
     }
 
-    pub fn from_lexer(lexer: Lexer<'a>) -> Self {
-        Parser { lexer }
+    pub fn with_config(mut self, config: ParserConfig) -> Self {
+        if config.allow_non_finite {
+            self.lexer = self.lexer.allow_non_finite();
+        }
+        if config.allow_digit_separators {
+            self.lexer = self.lexer.allow_digit_separators();
+        }
+        self.config = config;
+        self
+    }
+
+    /// Limit the number of elements a single array or object may contain.
+    pub fn with_max_elements(mut self, max_elements: usize) -> Self {
+        self.config.max_elements = Some(max_elements);
+        self
     }
 
+    /// Only accept documents where the top level value is an array or object.
+    pub fn require_top_level_container(mut self) -> Self {
+        self.config.require_top_level_container = true;
+        self
+    }
+
+    /// Reject bare identifiers like `foo` or `NaN` instead of reading them as references,
+    /// references can still be written with a `$` sigil.
+    pub fn strict(mut self) -> Self {
+        self.config.strict = true;
+        self
+    }
+
+    /// Let identical object keys share one allocation in the values from
+    /// [`Parser::to_owned_value`]. This saves memory for arrays of records with the same keys.
+    #[cfg(feature = "rc_keys")]
+    pub fn with_key_interning(mut self) -> Self {
+        self.interned_keys = Some(std::collections::HashSet::new());
+        self
+    }
+
+    /// Parse the first complete value of `text` and return it with the text that follows it,
+    /// for reading JSON values that are framed back to back without separators.
+    pub fn parse_one(text: &str) -> Result<(Value, &str), Error> {
+        let mut parser = Parser::from_str(text);
+        let value = parser.next_value()?.to_value();
+        Ok((value, &text[parser.lexer.bytes_consumed()..]))
+    }
+
+    /// Parse newline delimited JSON, where every line holds one complete value.
+    ///
+    /// Blank lines are skipped. A line that does not parse yields an error and parsing goes on
+    /// with the next line. Spans in errors are relative to the start of `text`.
+    pub fn parse_lines(text: &'a str) -> Lines<'a> {
+        Lines { text, offset: 0 }
+    }
+
+    /// Empty span at the end of the text, for errors about input that is missing.
+    fn end_of_input(&self) -> Span {
+        Span::new(self.lexer.text.len(), self.lexer.text.len())
+    }
+
+    fn next_value(&mut self) -> Result<ValueRef<'a>, Error> {
+        while let Some(token) = self.next_token()? {
+            if !token.is_whitespace() {
+                return self.value(token);
+            }
+        }
+
+        Err(Error::new(ErrorKind::None))
+    }
+
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_value(&mut self) -> Result<ValueRef<'a>, Error> {
         if self.lexer.is_error {
-            return Err(Error {
-                kind: ErrorKind::Lexer,
-            });
+            return Err(Error::new(ErrorKind::Lexer));
+        }
+
+        let value = self.next_value()?;
+        while let Some(token) = self.next_token()? {
+            if !token.is_whitespace() {
+                return Err(Error::with_span(ErrorKind::TrailingContent, token.span));
+            }
         }
 
-        self.to_value_inner(None)
+        if self.config.require_top_level_container
+            && !matches!(value, ValueRef::Array(_) | ValueRef::Object(_))
+        {
+            return Err(Error::new(ErrorKind::TopLevelScalar));
+        }
+
+        Ok(value)
     }
 
-    fn to_value_inner(&mut self, prev_token: Option<Token<'a>>) -> Result<ValueRef<'a>, Error> {
-        let mut item = None;
+    /// Like [`Parser::to_value`], but converted to an owned [`Value`], with interned keys when
+    /// [`Parser::with_key_interning`] is set.
+    #[cfg(feature = "rc_keys")]
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_owned_value(&mut self) -> Result<Value, Error> {
+        let value = self.to_value()?;
+        Ok(match &mut self.interned_keys {
+            Some(keys) => value.into_value_interned(keys),
+            None => value.to_value(),
+        })
+    }
 
-        if let Some(token) = prev_token {
-            self.inner_value_loop(&token, &mut item)?;
-            if let Some(item) = item {
-                return Ok(item);
+    /// Parse every value of a stream of concatenated values like `{"a":1}[2,3]true`, whitespace
+    /// between the values is allowed.
+    pub fn parse_many(&mut self) -> Result<Vec<ValueRef<'a>>, Error> {
+        let mut values = Vec::new();
+        while let Some(token) = self.next_token()? {
+            if !token.is_whitespace() {
+                values.push(self.value(token)?);
             }
         }
 
-        while let Some(token) = self.lexer.next() {
-            self.inner_value_loop(&token, &mut item)?;
-            // if let Some(item) = item {
-            //     return Ok(item);
-            // }
+        Ok(values)
+    }
+
+    /// The next token, or the span of the malformed input when the lexer stopped on it.
+    /// Comments are skipped when they are allowed.
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, Error> {
+        loop {
+            match self.lexer.next() {
+                None if self.lexer.is_error => {
+                    return Err(match self.lexer.error_span() {
+                        Some(span) => Error::with_span(ErrorKind::Lexer, span),
+                        None => Error::new(ErrorKind::Lexer),
+                    });
+                }
+                Some(token) if token.kind == TokenKind::Comment && self.config.allow_comments => {}
+                Some(token) if token.kind == TokenKind::Comment => {
+                    return Err(Error::invalid_token(token.span));
+                }
+                token => return Ok(token),
+            }
         }
+    }
 
-        if let Some(item) = item {
-            Ok(item)
-        } else {
-            Err(Error {
-                kind: ErrorKind::None,
-            })
+    /// Parse the value that starts with `token`.
+    ///
+    /// Arrays and objects that are still open are kept on an explicit stack instead of the call
+    /// stack, so deeply nested input can not overflow it. The depth is the size of that stack.
+    fn value(&mut self, token: Token<'a>) -> Result<ValueRef<'a>, Error> {
+        let mut stack = Vec::new();
+        let mut step = Step::Start(token);
+        loop {
+            step = match step {
+                Step::Start(token) => self.start_value(token, &mut stack)?,
+                Step::Done(value) => match stack.last_mut() {
+                    Some(frame) => {
+                        self.add_to_frame(frame, value)?;
+                        Step::Continue
+                    }
+                    None => return Ok(value),
+                },
+                Step::Continue => self.continue_frame(&mut stack)?,
+            };
         }
     }
 
-    fn inner_value_loop(
+    /// Parse a scalar, or open an array or object.
+    fn start_value(
         &mut self,
-        token: &Token<'a>,
-        item: &mut Option<ValueRef<'a>>,
-    ) -> Result<(), Error> {
-        match token.kind {
-            TokenKind::TabSpacing | TokenKind::NewLine | TokenKind::Spacing => {}
-            TokenKind::StartMapping => *item = Some(self.value_mapping()?),
-            TokenKind::EndMapping => return Err(Error::invalid_token()),
-            TokenKind::StartArray => *item = Some(self.value_array()?),
-            TokenKind::EndArray => return Err(Error::invalid_token()),
-            TokenKind::Separator => return Err(Error::invalid_token()),
-            TokenKind::KeySeparator => return Err(Error::invalid_token()),
-            TokenKind::String => *item = Some(Self::value_string(&token)?),
-            TokenKind::Integer => *item = Some(Self::value_integer(&token)?),
-            TokenKind::Boolean => *item = Some(Self::value_boolean(&token)?),
-            TokenKind::Float => *item = Some(Self::value_float(&token)?),
-            TokenKind::Reference => *item = Some(Self::value_reference(&token)?),
+        token: Token<'a>,
+        stack: &mut Vec<Frame<'a>>,
+    ) -> Result<Step<'a>, Error> {
+        let value = match token.kind {
+            TokenKind::StartMapping => {
+                let frame = Frame::Object {
+                    start: token.span,
+                    map: HashMap::new(),
+                    key: None,
+                    key_seperator: false,
+                    value_done: false,
+                };
+                return self.open(stack, frame, token.span);
+            }
+            TokenKind::StartArray => {
+                let frame = Frame::Array {
+                    start: token.span,
+                    values: Vec::new(),
+                    seperator: false,
+                    value_done: false,
+                };
+                return self.open(stack, frame, token.span);
+            }
+            TokenKind::String => Self::value_string(&token)?,
+            TokenKind::Integer | TokenKind::Float if self.config.preserve_number_text => {
+                Self::value_raw_number(&token, &self.config)?
+            }
+            TokenKind::Integer => Self::value_integer(&token, &self.config)?,
+            TokenKind::Boolean => Self::value_boolean(&token)?,
+            TokenKind::Null => ValueRef::Null,
+            TokenKind::Float => Self::value_float(&token, &self.config)?,
+            TokenKind::Reference if self.config.strict && !token.data.starts_with('$') => {
+                return Err(Error::with_span(
+                    ErrorKind::UnquotedIdentifier(token.data.to_string()),
+                    token.span,
+                ));
+            }
+            TokenKind::Reference => Self::value_reference(&token)?,
+            TokenKind::Error => return Err(Error::with_span(ErrorKind::Lexer, token.span)),
+            TokenKind::EndMapping
+            | TokenKind::EndArray
+            | TokenKind::Separator
+            | TokenKind::KeySeparator
+            | TokenKind::Spacing
+            | TokenKind::TabSpacing
+            | TokenKind::NewLine
+            | TokenKind::Comment => return Err(Error::invalid_token(token.span)),
         };
 
+        Ok(Step::Done(value))
+    }
+
+    fn open(
+        &self,
+        stack: &mut Vec<Frame<'a>>,
+        frame: Frame<'a>,
+        start: Span,
+    ) -> Result<Step<'a>, Error> {
+        if stack.len() >= self.config.max_depth {
+            return Err(Error::with_span(ErrorKind::DepthLimitExceeded, start));
+        }
+        stack.push(frame);
+        Ok(Step::Continue)
+    }
+
+    /// Read the next token of the innermost open array or object.
+    fn continue_frame(&mut self, stack: &mut Vec<Frame<'a>>) -> Result<Step<'a>, Error> {
+        let token = self.next_token()?;
+        let frame = stack
+            .last_mut()
+            .expect("only called with an open array or object");
+        match (frame, token) {
+            (
+                Frame::Array {
+                    seperator: true, ..
+                },
+                Some(token),
+            ) if token.kind == TokenKind::Separator => {
+                Err(Error::with_span(ErrorKind::DoubleSeparators, token.span))
+            }
+            (
+                Frame::Array {
+                    seperator,
+                    value_done,
+                    ..
+                },
+                Some(token),
+            ) if token.kind == TokenKind::Separator => {
+                *seperator = true;
+                *value_done = false;
+                Ok(Step::Continue)
+            }
+            (
+                Frame::Array {
+                    value_done: false, ..
+                },
+                Some(token),
+            ) if starts_value(&token) => Ok(Step::Start(token)),
+            (Frame::Array { .. }, Some(token)) if token.kind == TokenKind::EndArray => {
+                match stack.pop() {
+                    Some(Frame::Array { values, .. }) => Ok(Step::Done(ValueRef::Array(values))),
+                    _ => unreachable!("the innermost frame is an array"),
+                }
+            }
+            (
+                Frame::Object {
+                    map,
+                    key: key @ None,
+                    value_done: false,
+                    ..
+                },
+                Some(token),
+            ) if token.kind == TokenKind::String => {
+                let name = unescape(string_contents(token.data))
+                    .map_err(|kind| Error::with_span(kind, token.span))?;
+                if self.config.reject_duplicate_keys && map.contains_key(&name) {
+                    return Err(Error::with_span(
+                        ErrorKind::DuplicateKey(name.to_string()),
+                        token.span,
+                    ));
+                }
+                *key = Some(name);
+                Ok(Step::Continue)
+            }
+            (
+                Frame::Object {
+                    key_seperator: true,
+                    ..
+                },
+                Some(token),
+            ) if token.kind == TokenKind::KeySeparator => {
+                Err(Error::with_span(ErrorKind::DoubleKeySeparator, token.span))
+            }
+            (
+                Frame::Object {
+                    key: Some(_),
+                    key_seperator,
+                    ..
+                },
+                Some(token),
+            ) if token.kind == TokenKind::KeySeparator => {
+                *key_seperator = true;
+                Ok(Step::Continue)
+            }
+            (
+                Frame::Object {
+                    key_seperator: true,
+                    ..
+                },
+                Some(token),
+            ) if starts_value(&token) => Ok(Step::Start(token)),
+            (
+                Frame::Object {
+                    key: None,
+                    value_done,
+                    ..
+                },
+                Some(token),
+            ) if token.kind == TokenKind::Separator => {
+                *value_done = false;
+                Ok(Step::Continue)
+            }
+            (Frame::Object { key: None, .. }, Some(token))
+                if token.kind == TokenKind::EndMapping =>
+            {
+                match stack.pop() {
+                    Some(Frame::Object { map, .. }) => Ok(Step::Done(ValueRef::Object(map))),
+                    _ => unreachable!("the innermost frame is an object"),
+                }
+            }
+            (_, Some(token)) if token.is_whitespace() => Ok(Step::Continue),
+            (_, Some(token)) => Err(Error::invalid_token(token.span)),
+            (_, None) => Err(Error::invalid_token(self.end_of_input())),
+        }
+    }
+
+    /// Add a finished value to the innermost open array or object.
+    fn add_to_frame(&self, frame: &mut Frame<'a>, value: ValueRef<'a>) -> Result<(), Error> {
+        match frame {
+            Frame::Array {
+                start,
+                values,
+                seperator,
+                value_done,
+            } => {
+                values.push(value);
+                self.check_max_elements(values.len(), start)?;
+                *seperator = false;
+                *value_done = true;
+            }
+            Frame::Object {
+                start,
+                map,
+                key,
+                key_seperator,
+                value_done,
+            } => {
+                if let Some(key) = key.take() {
+                    map.insert(key, value);
+                }
+                self.check_max_elements(map.len(), start)?;
+                *key_seperator = false;
+                *value_done = true;
+            }
+        }
         Ok(())
     }
 
     fn value_string(token: &Token<'a>) -> Result<ValueRef<'a>, Error> {
-        Ok(ValueRef::String(&token.data.trim_matches('"')))
+        unescape(string_contents(token.data))
+            .map(ValueRef::String)
+            .map_err(|kind| Error::with_span(kind, token.span))
     }
 
+    /// References may start with a `$` sigil, which is not part of the name.
     fn value_reference(token: &Token<'a>) -> Result<ValueRef<'a>, Error> {
-        Ok(ValueRef::Reference(&token.data))
+        match token.data.strip_prefix('$').unwrap_or(token.data) {
+            "" => Err(Error::with_span(ErrorKind::EmptyReference, token.span)),
+            name => Ok(ValueRef::Reference(name, Some(token.span))),
+        }
     }
 
-    fn value_integer(token: &Token<'a>) -> Result<ValueRef<'a>, Error> {
-        token
-            .data
-            .parse()
-            .map(ValueRef::Integer)
-            .map_err(|_| Error {
-                kind: ErrorKind::InvalidInteger,
-            })
+    /// An integer that does not fit an `i64`, in either direction, becomes a `Number` with the
+    /// nearest `f64`, like most JSON parsers do.
+    fn value_integer(token: &Token<'a>, config: &ParserConfig) -> Result<ValueRef<'a>, Error> {
+        Self::check_leading_zeros(token, config)?;
+        let (negative, digits) = match token.data.as_bytes().first() {
+            Some(b'-') => (true, &token.data[1..]),
+            Some(b'+') if config.allow_plus_sign => (false, &token.data[1..]),
+            Some(b'+') => return Err(Error::with_span(ErrorKind::InvalidInteger, token.span)),
+            _ => (false, token.data),
+        };
+        let parsed = match digits.strip_prefix("0x") {
+            Some(hex) if config.allow_hex => i64::from_str_radix(hex, 16)
+                .map(|x| ValueRef::Integer(if negative { -x } else { x }))
+                .ok(),
+            Some(_) => None,
+            None => {
+                let text = strip_digit_separators(token.data);
+                match text.parse() {
+                    Ok(x) => Some(ValueRef::Integer(x)),
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow
+                        ) =>
+                    {
+                        text.parse().ok().map(ValueRef::Number)
+                    }
+                    Err(_) => None,
+                }
+            }
+        };
+
+        parsed.ok_or(Error::with_span(ErrorKind::InvalidInteger, token.span))
+    }
+
+    fn value_raw_number(token: &Token<'a>, config: &ParserConfig) -> Result<ValueRef<'a>, Error> {
+        match token.kind {
+            TokenKind::Float => Self::value_float(token, config)?,
+            _ => Self::value_integer(token, config)?,
+        };
+        Ok(ValueRef::RawNumber(token.data))
+    }
+
+    fn check_leading_zeros(token: &Token<'a>, config: &ParserConfig) -> Result<(), Error> {
+        let digits = token.data.trim_start_matches(['-', '+']).as_bytes();
+        match digits {
+            [b'0', b'0'..=b'9' | b'_', ..] if !config.allow_leading_zeros => {
+                Err(Error::with_span(ErrorKind::InvalidNumber, token.span))
+            }
+            _ => Ok(()),
+        }
     }
 
     fn value_boolean(token: &Token<'a>) -> Result<ValueRef<'a>, Error> {
@@ -127,111 +636,176 @@ impl<'a> Parser<'a> {
             .data
             .parse()
             .map(ValueRef::Boolean)
-            .map_err(|_| Error {
-                kind: ErrorKind::InvalidBoolean,
-            })
+            .map_err(|_| Error::with_span(ErrorKind::InvalidBoolean, token.span))
     }
 
-    fn value_float(token: &Token<'a>) -> Result<ValueRef<'a>, Error> {
-        token.data.parse().map(ValueRef::Number).map_err(|_| Error {
-            kind: ErrorKind::InvalidNumber,
-        })
+    fn value_float(token: &Token<'a>, config: &ParserConfig) -> Result<ValueRef<'a>, Error> {
+        Self::check_leading_zeros(token, config)?;
+        if token.data.starts_with('+') && !config.allow_plus_sign {
+            return Err(Error::with_span(ErrorKind::InvalidNumber, token.span));
+        }
+        if !config.allow_bare_decimal_point
+            && let Some((before, after)) = token.data.split_once('.')
+            && !(before.ends_with(|c: char| c.is_ascii_digit())
+                && after.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            return Err(Error::with_span(ErrorKind::BareDecimalPoint, token.span));
+        }
+
+        strip_digit_separators(token.data)
+            .parse()
+            .map(ValueRef::Number)
+            .map_err(|_| Error::with_span(ErrorKind::InvalidNumber, token.span))
     }
 
-    fn value_array(&mut self) -> Result<ValueRef<'a>, Error> {
-        let mut array = Vec::new();
-        let mut seperator = false;
+    fn check_max_elements(&self, count: usize, start: &Span) -> Result<(), Error> {
+        match self.config.max_elements {
+            Some(max_elements) if count > max_elements => Err(Error::with_span(
+                ErrorKind::TooManyElements,
+                Span::new(start.as_range().start, self.lexer.position),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
 
-        loop {
-            let item = self.lexer.next();
-            match item {
-                Some(Token {
-                    kind: TokenKind::Separator,
-                    ..
-                }) if seperator == true => {
-                    return Err(Error {
-                        kind: ErrorKind::DoubleSeparators,
-                    });
-                }
-                Some(Token {
-                    kind: TokenKind::Separator,
-                    ..
-                }) if seperator == false => {
-                    seperator = true;
-                }
-                Some(token)
-                    if (token.is_value(true)
-                        || token.kind == TokenKind::StartMapping
-                        || token.kind == TokenKind::StartArray) =>
-                {
-                    let value = self.to_value_inner(Some(token))?;
-                    array.push(value);
-                    seperator = false;
-                }
-                Some(Token {
-                    kind: TokenKind::EndArray,
-                    ..
-                }) => {
-                    return Ok(ValueRef::Array(array));
-                }
-                Some(token) if token.is_whitespace() => {}
-                _ => return Err(Error::invalid_token()),
+/// Iterator over the values of newline delimited JSON, see [`Parser::parse_lines`].
+#[derive(Debug, Clone)]
+pub struct Lines<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Result<ValueRef<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset < self.text.len() {
+            let start = self.offset;
+            let end = self.text[start..]
+                .find('\n')
+                .map_or(self.text.len(), |idx| start + idx);
+            self.offset = end + 1;
+
+            let line = &self.text[start..end];
+            if line.trim().is_empty() {
+                continue;
             }
+            let text = &self.text[..start + line.trim_end_matches('\r').len()];
+            return Some(Parser::from_str_at(text, start).and_then(|mut p| p.to_value()));
         }
+
+        None
     }
+}
 
-    fn value_mapping(&mut self) -> Result<ValueRef<'a>, Error> {
-        let mut map = HashMap::new();
+/// An array or object that is still being parsed.
+enum Frame<'a> {
+    Array {
+        start: Span,
+        values: Vec<ValueRef<'a>>,
+        seperator: bool,
+        value_done: bool,
+    },
+    Object {
+        start: Span,
+        map: HashMap<Cow<'a, str>, ValueRef<'a>>,
+        key: Option<Cow<'a, str>>,
+        key_seperator: bool,
+        value_done: bool,
+    },
+}
 
-        let mut key = None;
-        let mut key_seperator = false;
-        // let mut seperator = false;
+/// What [`Parser::value`] does next.
+enum Step<'a> {
+    /// Parse the value that starts with this token.
+    Start(Token<'a>),
+    /// A value is complete, add it to the innermost frame or return it.
+    Done(ValueRef<'a>),
+    /// Read the next token of the innermost frame.
+    Continue,
+}
 
-        loop {
-            let item = self.lexer.next();
-            // dbg!((&item, key, key_seperator));
-            match item {
-                Some(Token {
-                    kind: TokenKind::String,
-                    data,
-                    ..
-                }) => {
-                    key = Some(data.trim_matches('"'));
-                }
-                Some(Token {
-                    kind: TokenKind::KeySeparator,
-                    ..
-                }) if key.is_some() => {
-                    key_seperator = true;
-                }
-                Some(token)
-                    if key_seperator == true
-                        && (token.is_value(true)
-                            || token.kind == TokenKind::StartMapping
-                            || token.kind == TokenKind::StartArray)
-                        && key.is_some() =>
-                {
-                    let value = self.to_value_inner(Some(token))?;
-                    map.insert(key.unwrap(), value);
-                }
-                Some(Token {
-                    kind: TokenKind::Separator,
-                    ..
-                }) => {
-                    key_seperator = false;
-                    key = None;
-                }
-                Some(Token {
-                    kind: TokenKind::EndMapping,
-                    ..
-                }) => {
-                    return Ok(ValueRef::Object(map));
-                }
-                Some(token) if token.is_whitespace() => {}
-                _ => return Err(Error::invalid_token()),
-            }
+fn starts_value(token: &Token) -> bool {
+    token.is_value(true)
+        || token.kind == TokenKind::StartMapping
+        || token.kind == TokenKind::StartArray
+}
+
+/// The text of a number without `_` digit separators, borrowing from `text` when it has none.
+fn strip_digit_separators(text: &str) -> Cow<'_, str> {
+    match text.contains('_') {
+        true => Cow::Owned(text.replace('_', "")),
+        false => Cow::Borrowed(text),
+    }
+}
+
+/// The text between the quotes of a string token, escape sequences included.
+fn string_contents(data: &str) -> &str {
+    &data[1..data.len() - 1]
+}
+
+/// Decode the escape sequences of a JSON string without its quotes, borrowing from `text` when
+/// there is nothing to decode.
+fn unescape(text: &str) -> Result<Cow<'_, str>, ErrorKind> {
+    if !text.contains('\\') {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
         }
+
+        let escaped = match chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => unescape_unicode(&mut chars)?,
+            _ => return Err(ErrorKind::InvalidEscape),
+        };
+        out.push(escaped);
     }
+
+    Ok(Cow::Owned(out))
+}
+
+/// Decode the code point of a `\u` escape, `chars` starts after the `u`. Characters outside
+/// the basic multilingual plane are written as a surrogate pair of two escapes.
+fn unescape_unicode(chars: &mut std::str::Chars) -> Result<char, ErrorKind> {
+    let code = match hex4(chars)? {
+        high @ 0xD800..=0xDBFF => {
+            if chars.next() != Some('\\') || chars.next() != Some('u') {
+                return Err(ErrorKind::InvalidUnicodeEscape);
+            }
+            match hex4(chars)? {
+                low @ 0xDC00..=0xDFFF => 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00),
+                _ => return Err(ErrorKind::InvalidUnicodeEscape),
+            }
+        }
+        code => code,
+    };
+
+    // lone low surrogates are not a char either
+    char::from_u32(code).ok_or(ErrorKind::InvalidUnicodeEscape)
+}
+
+fn hex4(chars: &mut std::str::Chars) -> Result<u32, ErrorKind> {
+    (0..4).try_fold(0, |code, _| {
+        chars
+            .next()
+            .and_then(|ch| ch.to_digit(16))
+            .map(|digit| code * 16 + digit)
+            .ok_or(ErrorKind::InvalidUnicodeEscape)
+    })
 }
 
 #[test]
@@ -241,10 +815,22 @@ fn parse_integer() {
     assert_eq!(parser.to_value(), Ok(ValueRef::Integer(1234)))
 }
 
+#[test]
+fn parse_bare_float() {
+    assert_eq!(
+        Parser::from_str("12.5").to_value(),
+        Ok(ValueRef::Number(12.5))
+    );
+    assert_eq!(
+        Parser::from_str("3e-2").to_value(),
+        Ok(ValueRef::Number(3e-2))
+    );
+}
+
 #[test]
 fn parse_simple_map() {
     let mut parser = Parser::from_str(r#"{"a": 1234}"#);
-    let expected = HashMap::from_iter(vec![("a", ValueRef::Integer(1234))]);
+    let expected = HashMap::from_iter(vec![("a".into(), ValueRef::Integer(1234))]);
 
     assert_eq!(parser.to_value(), Ok(ValueRef::Object(expected)))
 }
@@ -253,7 +839,7 @@ fn parse_simple_map() {
 fn parse_simple_array() {
     let mut parser = Parser::from_str(r#"["test", 1, true, false, 912.21]"#);
     let expected = vec![
-        ValueRef::String("test"),
+        ValueRef::String("test".into()),
         ValueRef::Integer(1),
         ValueRef::Boolean(true),
         ValueRef::Boolean(false),
@@ -267,13 +853,1025 @@ fn parse_simple_array() {
 fn parse_map() {
     let mut parser = Parser::from_str(r#"{"a": 1234, "b": true, "c": {"d": false}}"#);
     let expected = HashMap::from_iter(vec![
-        ("a", ValueRef::Integer(1234)),
-        ("b", ValueRef::Boolean(true)),
+        ("a".into(), ValueRef::Integer(1234)),
+        ("b".into(), ValueRef::Boolean(true)),
         (
-            "c",
-            ValueRef::Object(HashMap::from_iter(vec![("d", ValueRef::Boolean(false))])),
+            "c".into(),
+            ValueRef::Object(HashMap::from_iter(vec![(
+                "d".into(),
+                ValueRef::Boolean(false),
+            )])),
         ),
     ]);
 
     assert_eq!(parser.to_value(), Ok(ValueRef::Object(expected)))
 }
+
+#[test]
+fn parse_double_key_separator() {
+    let mut parser = Parser::from_str(r#"{"a":: 1}"#);
+
+    assert_eq!(
+        parser.to_value(),
+        Err(Error::with_span(
+            ErrorKind::DoubleKeySeparator,
+            Span::new(5, 6)
+        ))
+    )
+}
+
+#[test]
+fn parse_max_elements() {
+    let text = format!("[{}]", vec!["1"; 1000].join(","));
+
+    let mut parser = Parser::from_str(&text).with_max_elements(1000);
+    assert!(parser.to_value().is_ok());
+
+    let mut parser = Parser::from_str(&text).with_max_elements(999);
+    assert_eq!(
+        parser.to_value(),
+        Err(Error::with_span(
+            ErrorKind::TooManyElements,
+            Span::new(0, 2000)
+        ))
+    );
+
+    let mut parser = Parser::from_str(r#"{"a": 1, "b": 2}"#).with_max_elements(1);
+    assert_eq!(
+        parser.to_value().map_err(|e| e.kind),
+        Err(ErrorKind::TooManyElements)
+    );
+}
+
+#[test]
+fn parse_top_level_container() {
+    let mut parser = Parser::from_str("1");
+    assert_eq!(parser.to_value(), Ok(ValueRef::Integer(1)));
+
+    let mut parser = Parser::from_str("1").require_top_level_container();
+    assert_eq!(
+        parser.to_value(),
+        Err(Error::new(ErrorKind::TopLevelScalar))
+    );
+
+    let mut parser = Parser::from_str("[1]");
+    assert_eq!(
+        parser.to_value(),
+        Ok(ValueRef::Array(vec![ValueRef::Integer(1)]))
+    );
+
+    let mut parser = Parser::from_str("[1]").require_top_level_container();
+    assert_eq!(
+        parser.to_value(),
+        Ok(ValueRef::Array(vec![ValueRef::Integer(1)]))
+    );
+}
+
+#[test]
+fn parse_string_map_value() {
+    let mut parser = Parser::from_str(r#"{"a": "b", "c": "d"}"#);
+    let expected = HashMap::from_iter(vec![
+        ("a".into(), ValueRef::String("b".into())),
+        ("c".into(), ValueRef::String("d".into())),
+    ]);
+
+    assert_eq!(parser.to_value(), Ok(ValueRef::Object(expected)))
+}
+
+#[test]
+fn parse_deep_alternating_nesting() {
+    let mut parser = Parser::from_str(r#"{"a":[{"b":[{"c":1}]}]}"#);
+    let expected = ValueRef::Object(HashMap::from_iter(vec![(
+        "a".into(),
+        ValueRef::Array(vec![ValueRef::Object(HashMap::from_iter(vec![(
+            "b".into(),
+            ValueRef::Array(vec![ValueRef::Object(HashMap::from_iter(vec![(
+                "c".into(),
+                ValueRef::Integer(1),
+            )]))]),
+        )]))]),
+    )]));
+
+    assert_eq!(parser.to_value(), Ok(expected))
+}
+
+#[test]
+fn parse_asymmetric_nesting() {
+    let mut parser = Parser::from_str(r#"[{"a":[1,{"b":2}],"c":"d"},[[{"e":[]}]],3]"#);
+    let expected = ValueRef::Array(vec![
+        ValueRef::Object(HashMap::from_iter(vec![
+            (
+                "a".into(),
+                ValueRef::Array(vec![
+                    ValueRef::Integer(1),
+                    ValueRef::Object(HashMap::from_iter(vec![("b".into(), ValueRef::Integer(2))])),
+                ]),
+            ),
+            ("c".into(), ValueRef::String("d".into())),
+        ])),
+        ValueRef::Array(vec![ValueRef::Array(vec![ValueRef::Object(
+            HashMap::from_iter(vec![("e".into(), ValueRef::Array(Vec::new()))]),
+        )])]),
+        ValueRef::Integer(3),
+    ]);
+    assert_eq!(parser.to_value(), Ok(expected));
+
+    let mut parser = Parser::from_str(r#"{"a":{"b":{}},"c":[[],[true]],"d":false}"#);
+    let expected = ValueRef::Object(HashMap::from_iter(vec![
+        (
+            "a".into(),
+            ValueRef::Object(HashMap::from_iter(vec![(
+                "b".into(),
+                ValueRef::Object(HashMap::new()),
+            )])),
+        ),
+        (
+            "c".into(),
+            ValueRef::Array(vec![
+                ValueRef::Array(Vec::new()),
+                ValueRef::Array(vec![ValueRef::Boolean(true)]),
+            ]),
+        ),
+        ("d".into(), ValueRef::Boolean(false)),
+    ]));
+    assert_eq!(parser.to_value(), Ok(expected));
+}
+
+#[test]
+fn parse_missing_separators() {
+    assert_eq!(
+        Parser::from_str(r#"{"a": 1 "b": 2}"#).to_value(),
+        Err(Error::invalid_token(Span::new(8, 11)))
+    );
+    assert_eq!(
+        Parser::from_str(r#"[[1] [2]]"#).to_value(),
+        Err(Error::invalid_token(Span::new(5, 6)))
+    );
+}
+
+#[test]
+fn parse_sigil_reference() {
+    let mut parser = Parser::from_str("[$my_ref, other_ref]");
+
+    assert_eq!(
+        parser.to_value(),
+        Ok(ValueRef::Array(vec![
+            ValueRef::Reference("my_ref", Some(Span::new(1, 8))),
+            ValueRef::Reference("other_ref", Some(Span::new(10, 19)))
+        ]))
+    )
+}
+
+#[test]
+fn parse_empty_reference() {
+    let mut parser = Parser::from_str("[$]");
+
+    assert_eq!(
+        parser.to_value(),
+        Err(Error::with_span(ErrorKind::EmptyReference, Span::new(1, 2)))
+    )
+}
+
+#[test]
+fn parse_from_offset() {
+    let text = r#"payload = {"a": [1, 2]}"#;
+    let start = text.find('{').unwrap();
+
+    let mut parser = Parser::from_str_at(text, start).unwrap();
+    let expected = HashMap::from_iter(vec![(
+        "a".into(),
+        ValueRef::Array(vec![ValueRef::Integer(1), ValueRef::Integer(2)]),
+    )]);
+    assert_eq!(parser.to_value(), Ok(ValueRef::Object(expected)));
+
+    let mut parser = Parser::from_str_at(text, start).unwrap();
+    parser.lexer.next();
+    assert_eq!(parser.lexer.next().unwrap().span, Span::new(11, 14));
+
+    assert_eq!(
+        Parser::from_str_at("é[1]", 1).map(|_| ()),
+        Err(Error::new(ErrorKind::InvalidOffset))
+    );
+}
+
+#[test]
+fn parse_exponent_without_digits() {
+    assert_eq!(
+        Parser::from_str("[1, 1e]").to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(4, 6)))
+    );
+    assert_eq!(
+        Parser::from_str("1e-").to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(0, 3)))
+    );
+    assert_eq!(
+        Parser::from_str("1e5").to_value(),
+        Ok(ValueRef::Number(1e5))
+    );
+}
+
+#[test]
+fn parse_strict_unquoted_identifier() {
+    let error = Parser::from_str("[foo]").strict().to_value().unwrap_err();
+
+    assert_eq!(
+        error,
+        Error::with_span(
+            ErrorKind::UnquotedIdentifier("foo".to_string()),
+            Span::new(1, 4)
+        )
+    );
+    assert!(error.to_string().contains("did you mean \"foo\"?"));
+
+    let mut parser = Parser::from_str("[$foo]").strict();
+    assert_eq!(
+        parser.to_value(),
+        Ok(ValueRef::Array(vec![ValueRef::Reference(
+            "foo",
+            Some(Span::new(1, 5))
+        )]))
+    );
+}
+
+#[test]
+fn parse_reference_at_end_of_input() {
+    assert_eq!(
+        Parser::from_str("some_ref").to_value(),
+        Ok(ValueRef::Reference("some_ref", Some(Span::new(0, 8))))
+    );
+    assert_eq!(
+        Parser::from_str("some_ref \n").to_value(),
+        Ok(ValueRef::Reference("some_ref", Some(Span::new(0, 8))))
+    );
+    assert_eq!(
+        Parser::from_str("x").to_value(),
+        Ok(ValueRef::Reference("x", Some(Span::new(0, 1))))
+    );
+    assert_eq!(
+        Parser::from_str("[1, some_ref]").to_value(),
+        Ok(ValueRef::Array(vec![
+            ValueRef::Integer(1),
+            ValueRef::Reference("some_ref", Some(Span::new(4, 12)))
+        ]))
+    );
+}
+
+#[test]
+fn parse_boolean_terminators() {
+    assert_eq!(
+        Parser::from_str("true").to_value(),
+        Ok(ValueRef::Boolean(true))
+    );
+    assert_eq!(
+        Parser::from_str("false").to_value(),
+        Ok(ValueRef::Boolean(false))
+    );
+    assert_eq!(
+        Parser::from_str("[true]").to_value(),
+        Ok(ValueRef::Array(vec![ValueRef::Boolean(true)]))
+    );
+    assert_eq!(
+        Parser::from_str("[false,true]").to_value(),
+        Ok(ValueRef::Array(vec![
+            ValueRef::Boolean(false),
+            ValueRef::Boolean(true)
+        ]))
+    );
+    assert_eq!(
+        Parser::from_str(r#"{"a":false}"#).to_value(),
+        Ok(ValueRef::Object(HashMap::from_iter(vec![(
+            "a".into(),
+            ValueRef::Boolean(false)
+        )])))
+    );
+}
+
+#[test]
+fn parse_null() {
+    assert_eq!(Parser::from_str("null").to_value(), Ok(ValueRef::Null));
+    assert_eq!(
+        Parser::from_str("[1, null]").to_value(),
+        Ok(ValueRef::Array(vec![ValueRef::Integer(1), ValueRef::Null]))
+    );
+    assert_eq!(
+        Parser::from_str(r#"{"a": null, "b": nullable}"#).to_value(),
+        Ok(ValueRef::Object(HashMap::from_iter(vec![
+            ("a".into(), ValueRef::Null),
+            (
+                "b".into(),
+                ValueRef::Reference("nullable", Some(Span::new(17, 25)))
+            )
+        ])))
+    );
+}
+
+#[test]
+fn parse_multibyte() {
+    assert_eq!(
+        Parser::from_str(r#"{"naïve": ["café", "🦀", true]}"#).to_value(),
+        Ok(ValueRef::Object(HashMap::from_iter(vec![(
+            "naïve".into(),
+            ValueRef::Array(vec![
+                ValueRef::String("café".into()),
+                ValueRef::String("🦀".into()),
+                ValueRef::Boolean(true)
+            ])
+        )])))
+    );
+    assert_eq!(
+        Parser::from_str("[\"é\", 1.2.3]").to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(7, 11)))
+    );
+}
+
+#[test]
+fn parse_one_back_to_back() {
+    let (first, rest) = Parser::parse_one("{}[]").unwrap();
+    assert_eq!(first, Value::Object(HashMap::new()));
+    assert_eq!(rest, "[]");
+
+    let (second, rest) = Parser::parse_one(rest).unwrap();
+    assert_eq!(second, Value::Array(Vec::new()));
+    assert_eq!(rest, "");
+
+    assert_eq!(Parser::parse_one(" \n"), Err(Error::new(ErrorKind::None)));
+}
+
+#[test]
+fn parse_unterminated_string() {
+    assert_eq!(
+        Parser::from_str(r#""abc"#).to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(0, 4)))
+    );
+    assert_eq!(
+        Parser::from_str(r#"["a", "b]"#).to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(6, 9)))
+    );
+}
+
+#[test]
+fn parse_string_escapes() {
+    let cases = [
+        (r#""a\nb""#, "a\nb"),
+        (r#""a\tb""#, "a\tb"),
+        (r#""a\rb""#, "a\rb"),
+        (r#""a\\b""#, "a\\b"),
+        (r#""a\"b""#, "a\"b"),
+        (r#""a\/b""#, "a/b"),
+        (r#""a\bb""#, "a\u{8}b"),
+        (r#""a\fb""#, "a\u{c}b"),
+        (r#""\\""#, "\\"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(
+            Parser::from_str(text).to_value(),
+            Ok(ValueRef::String(expected.into())),
+            "{}",
+            text
+        );
+    }
+
+    assert!(matches!(
+        Parser::from_str(r#""plain""#).to_value(),
+        Ok(ValueRef::String(Cow::Borrowed("plain")))
+    ));
+    assert_eq!(
+        Parser::from_str(r#"["a\xb"]"#).to_value(),
+        Err(Error::with_span(ErrorKind::InvalidEscape, Span::new(1, 7)))
+    );
+}
+
+#[test]
+fn parse_string_ending_in_escaped_quote() {
+    assert_eq!(
+        Parser::from_str(r#"["say \"hi\""]"#).to_value(),
+        Ok(ValueRef::Array(vec![ValueRef::String("say \"hi\"".into())]))
+    );
+    assert_eq!(
+        Parser::from_str(r#"{"q": "a\""}"#).to_value(),
+        Ok(ValueRef::Object(HashMap::from_iter(vec![(
+            "q".into(),
+            ValueRef::String("a\"".into())
+        )])))
+    );
+}
+
+#[test]
+fn parse_escaped_keys() {
+    assert_eq!(
+        Parser::from_str(r#"{"a\"": 1, "a\nb": 2}"#).to_value(),
+        Ok(ValueRef::Object(HashMap::from_iter(vec![
+            ("a\"".into(), ValueRef::Integer(1)),
+            ("a\nb".into(), ValueRef::Integer(2)),
+        ])))
+    );
+
+    let text = r#"{"a\"":1}"#;
+    let value = Parser::from_str(text).to_value().unwrap();
+    assert_eq!(value.to_string(), text);
+    assert_eq!(Parser::from_str(&value.to_string()).to_value(), Ok(value));
+
+    let config = ParserConfig {
+        reject_duplicate_keys: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        Parser::from_str(r#"{"a": 1, "\u0061": 2}"#)
+            .with_config(config)
+            .to_value(),
+        Err(Error::with_span(
+            ErrorKind::DuplicateKey("a".to_string()),
+            Span::new(9, 17)
+        ))
+    );
+}
+
+#[test]
+fn parse_unicode_escapes() {
+    assert_eq!(
+        Parser::from_str(r#""caf\u00e9""#).to_value(),
+        Ok(ValueRef::String("café".into()))
+    );
+    assert_eq!(
+        Parser::from_str(r#""\uD834\uDD1E""#).to_value(),
+        Ok(ValueRef::String("\u{1D11E}".into()))
+    );
+    assert_eq!(
+        Parser::from_str(r#"["\uD834"]"#).to_value(),
+        Err(Error::with_span(
+            ErrorKind::InvalidUnicodeEscape,
+            Span::new(1, 9)
+        ))
+    );
+    assert_eq!(
+        Parser::from_str(r#""\uDD1E""#).to_value().map(|_| ()),
+        Err(Error::with_span(
+            ErrorKind::InvalidUnicodeEscape,
+            Span::new(0, 8)
+        ))
+    );
+    assert_eq!(
+        Parser::from_str(r#""\u00g9""#).to_value().map(|_| ()),
+        Err(Error::with_span(
+            ErrorKind::InvalidUnicodeEscape,
+            Span::new(0, 8)
+        ))
+    );
+}
+
+#[test]
+fn error_display() {
+    let error = Parser::from_str("[1,,2]").to_value().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::DoubleSeparators);
+    assert_eq!(error.to_string(), "double separators at 3..4");
+
+    assert_eq!(
+        Error::invalid_token(Span::new(0, 1)).to_string(),
+        "invalid token at 0..1"
+    );
+    assert_eq!(
+        Error::new(ErrorKind::TopLevelScalar).to_string(),
+        "top level value is not an array or object"
+    );
+
+    let boxed: Box<dyn std::error::Error> = Box::new(error);
+    assert_eq!(boxed.to_string(), "double separators at 3..4");
+}
+
+#[test]
+fn parse_error_spans() {
+    let text = "[1,,2]";
+    let error = Parser::from_str(text).to_value().unwrap_err();
+    assert_eq!(error.span(), Some(&Span::new(3, 4)));
+    assert_eq!(&text[error.span().unwrap().as_range()], ",");
+
+    assert_eq!(
+        Parser::from_str("[1, ]]").to_value(),
+        Err(Error::with_span(
+            ErrorKind::TrailingContent,
+            Span::new(5, 6)
+        ))
+    );
+    assert_eq!(
+        Parser::from_str("{]").to_value(),
+        Err(Error::invalid_token(Span::new(1, 2)))
+    );
+    assert_eq!(
+        Parser::from_str("[1").to_value(),
+        Err(Error::invalid_token(Span::new(2, 2)))
+    );
+}
+
+#[test]
+fn parse_line_comments() {
+    let text = "{\n  \"a\": 1, // the first\n  \"b\": [2, // inside\n 3] // end\n}";
+    let config = ParserConfig {
+        allow_comments: true,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        Parser::from_str(text).with_config(config).to_value(),
+        Ok(ValueRef::Object(HashMap::from_iter(vec![
+            ("a".into(), ValueRef::Integer(1)),
+            (
+                "b".into(),
+                ValueRef::Array(vec![ValueRef::Integer(2), ValueRef::Integer(3)])
+            )
+        ])))
+    );
+    assert_eq!(
+        Parser::from_str("[1, // inside\n 2]").to_value(),
+        Err(Error::invalid_token(Span::new(4, 13)))
+    );
+}
+
+#[test]
+fn parse_block_comments() {
+    let config = ParserConfig {
+        allow_comments: true,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        Parser::from_str("/* header\n spanning lines */ [1, /* two */ 2]")
+            .with_config(config.clone())
+            .to_value(),
+        Ok(ValueRef::Array(vec![
+            ValueRef::Integer(1),
+            ValueRef::Integer(2)
+        ]))
+    );
+    assert_eq!(
+        Parser::from_str("[1] /* open")
+            .with_config(config)
+            .to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(4, 11)))
+    );
+}
+
+#[test]
+fn parse_duplicate_keys() {
+    let config = ParserConfig {
+        reject_duplicate_keys: true,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        Parser::from_str(r#"{"a": 1, "a": 2}"#)
+            .with_config(config.clone())
+            .to_value(),
+        Err(Error::with_span(
+            ErrorKind::DuplicateKey("a".to_string()),
+            Span::new(9, 12)
+        ))
+    );
+    assert_eq!(
+        Parser::from_str(r#"{"a": 1, "a": 2}"#).to_value(),
+        Ok(ValueRef::Object(HashMap::from_iter(vec![(
+            "a".into(),
+            ValueRef::Integer(2)
+        )])))
+    );
+
+    let siblings = r#"{"a": {"id": 1}, "b": {"id": 2}, "c": [{"id": 3}, {"id": 4}]}"#;
+    assert!(
+        Parser::from_str(siblings)
+            .with_config(config)
+            .to_value()
+            .is_ok()
+    );
+}
+
+#[test]
+fn parse_max_depth() {
+    let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    let config = ParserConfig {
+        max_depth: 4,
+        ..Default::default()
+    };
+
+    let text = nested(4);
+    assert!(
+        Parser::from_str(&text)
+            .with_config(config.clone())
+            .to_value()
+            .is_ok()
+    );
+    let text = nested(5);
+    assert_eq!(
+        Parser::from_str(&text)
+            .with_config(config.clone())
+            .to_value(),
+        Err(Error::with_span(
+            ErrorKind::DepthLimitExceeded,
+            Span::new(4, 5)
+        ))
+    );
+
+    // siblings at the limit are fine, the depth goes back down after every container
+    let text = r#"[[{"a": [1]}], [{"b": []}], [[[]]]]"#;
+    assert!(
+        Parser::from_str(text)
+            .with_config(config)
+            .to_value()
+            .is_ok()
+    );
+
+    assert!(Parser::from_str(&nested(128)).to_value().is_ok());
+    assert_eq!(
+        Parser::from_str(&nested(129)).to_value().map(|_| ()),
+        Err(Error::with_span(
+            ErrorKind::DepthLimitExceeded,
+            Span::new(128, 129)
+        ))
+    );
+}
+
+#[test]
+fn parse_deeply_nested_without_recursion() {
+    let depth = 100_000;
+    let text = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    let config = ParserConfig {
+        max_depth: usize::MAX,
+        ..Default::default()
+    };
+
+    // taken apart level by level, dropping the value as a whole would recurse as deep
+    let mut value = Parser::from_str(&text)
+        .with_config(config)
+        .to_value()
+        .unwrap();
+    let mut levels = 0;
+    while let ValueRef::Array(mut values) = value {
+        levels += 1;
+        match values.pop() {
+            Some(inner) => value = inner,
+            None => break,
+        }
+    }
+    assert_eq!(levels, depth);
+}
+
+#[test]
+fn parse_trailing_content() {
+    assert_eq!(
+        Parser::from_str("1 2").to_value(),
+        Err(Error::with_span(
+            ErrorKind::TrailingContent,
+            Span::new(2, 3)
+        ))
+    );
+    assert_eq!(
+        Parser::from_str("[1][2]").to_value(),
+        Err(Error::with_span(
+            ErrorKind::TrailingContent,
+            Span::new(3, 4)
+        ))
+    );
+    assert_eq!(
+        Parser::from_str("[1] \n\t").to_value(),
+        Ok(ValueRef::Array(vec![ValueRef::Integer(1)]))
+    );
+
+    let config = ParserConfig {
+        allow_comments: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        Parser::from_str("[1] // done")
+            .with_config(config)
+            .to_value(),
+        Ok(ValueRef::Array(vec![ValueRef::Integer(1)]))
+    );
+}
+
+#[test]
+fn parse_lines() {
+    let text = "{\"a\": 1}\n\n{\"a\": 2}\r\n{\"a\": 3}\n";
+    let values: Vec<_> = Parser::parse_lines(text).collect();
+    assert_eq!(
+        values,
+        (1..=3)
+            .map(|i| Ok(ValueRef::Object(HashMap::from_iter(vec![(
+                "a".into(),
+                ValueRef::Integer(i)
+            )]))))
+            .collect::<Vec<_>>()
+    );
+
+    let text = "[1]\n[2,,]\n[3]";
+    let values: Vec<_> = Parser::parse_lines(text).collect();
+    assert_eq!(
+        values,
+        vec![
+            Ok(ValueRef::Array(vec![ValueRef::Integer(1)])),
+            Err(Error::with_span(
+                ErrorKind::DoubleSeparators,
+                Span::new(7, 8)
+            )),
+            Ok(ValueRef::Array(vec![ValueRef::Integer(3)])),
+        ]
+    );
+}
+
+#[test]
+fn parse_many() {
+    let mut parser = Parser::from_str("{\"a\":1}[2,3]true \n");
+    assert_eq!(
+        parser.parse_many(),
+        Ok(vec![
+            ValueRef::Object(HashMap::from_iter(vec![("a".into(), ValueRef::Integer(1))])),
+            ValueRef::Array(vec![ValueRef::Integer(2), ValueRef::Integer(3)]),
+            ValueRef::Boolean(true),
+        ])
+    );
+
+    assert_eq!(Parser::from_str(" ").parse_many(), Ok(Vec::new()));
+    assert_eq!(
+        Parser::from_str("[1] ]").parse_many(),
+        Err(Error::invalid_token(Span::new(4, 5)))
+    );
+}
+
+#[test]
+fn parse_hex_integer() {
+    let config = ParserConfig {
+        allow_hex: true,
+        allow_leading_zeros: true,
+        ..Default::default()
+    };
+    let parse = |text| {
+        Parser::from_str(text)
+            .with_config(config.clone())
+            .to_value()
+    };
+
+    assert_eq!(parse("0xFF"), Ok(ValueRef::Integer(255)));
+    assert_eq!(parse("0x0"), Ok(ValueRef::Integer(0)));
+    assert_eq!(parse("-0xff00ff"), Ok(ValueRef::Integer(-0xff00ff)));
+    assert_eq!(
+        parse("[07, 0]"),
+        Ok(ValueRef::Array(vec![
+            ValueRef::Integer(7),
+            ValueRef::Integer(0)
+        ]))
+    );
+    assert_eq!(
+        parse("0x"),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(0, 2)))
+    );
+
+    assert_eq!(
+        Parser::from_str("0xFF").to_value(),
+        Err(Error::with_span(ErrorKind::InvalidInteger, Span::new(0, 4)))
+    );
+    assert_eq!(
+        parse("[-0x8000000000000000]"),
+        Err(Error::with_span(
+            ErrorKind::InvalidInteger,
+            Span::new(1, 20)
+        ))
+    );
+}
+
+#[test]
+fn parse_exponent_sign() {
+    assert_eq!(
+        Parser::from_str("[1e+10, 1.5e+3, -2e-2]").to_value(),
+        Ok(ValueRef::Array(vec![
+            ValueRef::Number(1e10),
+            ValueRef::Number(1500.0),
+            ValueRef::Number(-0.02),
+        ]))
+    );
+}
+
+#[test]
+fn parse_plus_sign() {
+    assert_eq!(
+        Parser::from_str("+5").to_value(),
+        Err(Error::with_span(ErrorKind::InvalidInteger, Span::new(0, 2)))
+    );
+    assert_eq!(
+        Parser::from_str("[1, +1.5]").to_value(),
+        Err(Error::with_span(ErrorKind::InvalidNumber, Span::new(4, 8)))
+    );
+
+    let config = ParserConfig {
+        allow_plus_sign: true,
+        allow_hex: true,
+        ..Default::default()
+    };
+    let parse = |text| {
+        Parser::from_str(text)
+            .with_config(config.clone())
+            .to_value()
+    };
+    assert_eq!(parse("+5"), Ok(ValueRef::Integer(5)));
+    assert_eq!(parse("+1.5e+1"), Ok(ValueRef::Number(15.0)));
+    assert_eq!(parse("+0x10"), Ok(ValueRef::Integer(16)));
+}
+
+#[test]
+fn parse_leading_zeros() {
+    assert_eq!(Parser::from_str("0").to_value(), Ok(ValueRef::Integer(0)));
+    assert_eq!(
+        Parser::from_str("0.5").to_value(),
+        Ok(ValueRef::Number(0.5))
+    );
+    assert_eq!(Parser::from_str("-0").to_value(), Ok(ValueRef::Integer(0)));
+    assert_eq!(
+        Parser::from_str("[1, 007]").to_value(),
+        Err(Error::with_span(ErrorKind::InvalidNumber, Span::new(4, 7)))
+    );
+    assert_eq!(
+        Parser::from_str("-01.5").to_value(),
+        Err(Error::with_span(ErrorKind::InvalidNumber, Span::new(0, 5)))
+    );
+
+    let config = ParserConfig {
+        allow_leading_zeros: true,
+        ..Default::default()
+    };
+    let parse = |text| {
+        Parser::from_str(text)
+            .with_config(config.clone())
+            .to_value()
+    };
+    assert_eq!(parse("007"), Ok(ValueRef::Integer(7)));
+    assert_eq!(parse("01.5"), Ok(ValueRef::Number(1.5)));
+}
+
+#[test]
+fn parse_bare_decimal_point() {
+    assert_eq!(
+        Parser::from_str("[1.]").to_value(),
+        Err(Error::with_span(
+            ErrorKind::BareDecimalPoint,
+            Span::new(1, 3)
+        ))
+    );
+    assert_eq!(
+        Parser::from_str(".5").to_value(),
+        Err(Error::with_span(
+            ErrorKind::BareDecimalPoint,
+            Span::new(0, 2)
+        ))
+    );
+    assert_eq!(
+        Parser::from_str("1.e5").to_value(),
+        Err(Error::with_span(
+            ErrorKind::BareDecimalPoint,
+            Span::new(0, 4)
+        ))
+    );
+
+    let config = ParserConfig {
+        allow_bare_decimal_point: true,
+        ..Default::default()
+    };
+    let parse = |text| {
+        Parser::from_str(text)
+            .with_config(config.clone())
+            .to_value()
+    };
+    assert_eq!(
+        parse("[1., .5, -.5]"),
+        Ok(ValueRef::Array(vec![
+            ValueRef::Number(1.0),
+            ValueRef::Number(0.5),
+            ValueRef::Number(-0.5),
+        ]))
+    );
+}
+
+#[test]
+fn parse_non_finite() {
+    let config = ParserConfig {
+        allow_non_finite: true,
+        ..Default::default()
+    };
+    let parse = |text| {
+        Parser::from_str(text)
+            .with_config(config.clone())
+            .to_value()
+    };
+
+    assert_eq!(parse("Infinity"), Ok(ValueRef::Number(f64::INFINITY)));
+    assert_eq!(parse("-Infinity"), Ok(ValueRef::Number(f64::NEG_INFINITY)));
+    assert!(matches!(parse("NaN"), Ok(ValueRef::Number(x)) if x.is_nan()));
+    assert_ne!(parse("NaN"), parse("NaN"));
+    assert_eq!(
+        parse("Infinity_ref"),
+        Ok(ValueRef::Reference("Infinity_ref", Some(Span::new(0, 12))))
+    );
+
+    assert_eq!(
+        Parser::from_str("Infinity").to_value(),
+        Ok(ValueRef::Reference("Infinity", Some(Span::new(0, 8))))
+    );
+}
+
+#[test]
+fn parse_digit_separators() {
+    let config = ParserConfig {
+        allow_digit_separators: true,
+        ..Default::default()
+    };
+    let parse = |text| {
+        Parser::from_str(text)
+            .with_config(config.clone())
+            .to_value()
+    };
+
+    assert_eq!(parse("1_000"), Ok(ValueRef::Integer(1000)));
+    assert_eq!(parse("1_000.5"), Ok(ValueRef::Number(1000.5)));
+    assert_eq!(
+        parse("1_"),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(0, 2)))
+    );
+    assert_eq!(
+        parse("1__0"),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(0, 2)))
+    );
+    assert_eq!(
+        parse("0_1"),
+        Err(Error::with_span(ErrorKind::InvalidNumber, Span::new(0, 3)))
+    );
+    // a leading underscore starts an identifier, not a number
+    assert_eq!(
+        parse("_1"),
+        Ok(ValueRef::Reference("_1", Some(Span::new(0, 2))))
+    );
+    assert_eq!(
+        Parser::from_str("_1").strict().to_value(),
+        Err(Error::with_span(
+            ErrorKind::UnquotedIdentifier("_1".to_string()),
+            Span::new(0, 2)
+        ))
+    );
+}
+
+#[test]
+fn parse_integer_overflow() {
+    assert_eq!(
+        Parser::from_str("9223372036854775807").to_value(),
+        Ok(ValueRef::Integer(i64::MAX))
+    );
+    assert_eq!(
+        Parser::from_str("-9223372036854775808").to_value(),
+        Ok(ValueRef::Integer(i64::MIN))
+    );
+    assert_eq!(
+        Parser::from_str("18446744073709551615").to_value(),
+        Ok(ValueRef::Number(u64::MAX as f64))
+    );
+    assert_eq!(
+        Parser::from_str("[100000000000000000000000, -9223372036854775809]").to_value(),
+        Ok(ValueRef::Array(vec![
+            ValueRef::Number(1e23),
+            ValueRef::Number(-9223372036854775809.0),
+        ]))
+    );
+}
+
+#[test]
+fn parse_sign_without_number() {
+    assert_eq!(
+        Parser::from_str("+ 5").to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(0, 1)))
+    );
+    assert_eq!(
+        Parser::from_str("[+]").to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(1, 2)))
+    );
+    assert_eq!(
+        Parser::from_str("[- 1]").to_value(),
+        Err(Error::with_span(ErrorKind::Lexer, Span::new(1, 2)))
+    );
+}
+
+#[cfg(feature = "rc_keys")]
+#[test]
+fn parse_key_interning() {
+    use std::rc::Rc;
+
+    fn key(value: &Value, index: usize, name: &str) -> Rc<str> {
+        let object = value.get_index(index).and_then(Value::as_object).unwrap();
+        object.get_key_value(name).unwrap().0.clone()
+    }
+
+    let text = r#"[{"id": 1, "a\nb": 2}, {"id": 3, "a\nb": 4}]"#;
+
+    let value = Parser::from_str(text)
+        .with_key_interning()
+        .to_owned_value()
+        .unwrap();
+    assert!(Rc::ptr_eq(&key(&value, 0, "id"), &key(&value, 1, "id")));
+    assert!(Rc::ptr_eq(&key(&value, 0, "a\nb"), &key(&value, 1, "a\nb")));
+
+    let value = Parser::from_str(text).to_owned_value().unwrap();
+    assert!(!Rc::ptr_eq(&key(&value, 0, "id"), &key(&value, 1, "id")));
+    assert_eq!(value, Parser::from_str(text).to_value().unwrap().to_value());
+}
@@ -0,0 +1,239 @@
+//! `serde` support for [`Value`], and serializing [`ValueRef`].
+//!
+//! A [`Value::Reference`] has no counterpart in the serde data model, so it is represented as
+//! a map with a single `"$ref"` entry holding the name, e.g. `{"$ref": "name"}`. Deserializing
+//! such a map gives back the `Reference`. A [`ValueRef::Reference`] serializes the same way.
+//!
+//! A raw number is serialized as the integer or float it spells, extensions like hexadecimal
+//! integers and digit separators included, or as a string when it spells no number.
+//!
+//! When deserializing, integral numbers that fit an `i64` become [`Value::Integer`], whether
+//! the format gave them as an integer or a float, so `2.0` becomes `2`. Everything else
+//! becomes a [`Value::Number`].
+
+use std::{collections::HashMap, fmt};
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{MapAccess, SeqAccess, Visitor},
+    ser::SerializeMap,
+};
+
+use crate::value::{Value, ValueRef, parse_raw_number};
+
+const REFERENCE_KEY: &str = "$ref";
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::String(x) => serializer.serialize_str(x),
+            Value::Integer(x) => serializer.serialize_i64(*x),
+            Value::Number(x) => serializer.serialize_f64(*x),
+            Value::Boolean(x) => serializer.serialize_bool(*x),
+            Value::Array(values) => serializer.collect_seq(values),
+            Value::Object(hash_map) => serializer.collect_map(hash_map),
+            Value::Reference(x) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(REFERENCE_KEY, x)?;
+                map.end()
+            }
+            Value::Null => serializer.serialize_unit(),
+            Value::RawNumber(x) => serialize_raw_number(x, serializer),
+        }
+    }
+}
+
+impl Serialize for ValueRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ValueRef::String(x) => serializer.serialize_str(x),
+            ValueRef::Integer(x) => serializer.serialize_i64(*x),
+            ValueRef::Number(x) => serializer.serialize_f64(*x),
+            ValueRef::Boolean(x) => serializer.serialize_bool(*x),
+            ValueRef::Array(value_refs) => serializer.collect_seq(value_refs),
+            ValueRef::Object(hash_map) => serializer.collect_map(hash_map),
+            ValueRef::Reference(x, _) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(REFERENCE_KEY, x)?;
+                map.end()
+            }
+            ValueRef::Null => serializer.serialize_unit(),
+            ValueRef::RawNumber(x) => serialize_raw_number(x, serializer),
+        }
+    }
+}
+
+fn serialize_raw_number<S: Serializer>(text: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    match parse_raw_number(text) {
+        Some(Value::Integer(x)) => serializer.serialize_i64(x),
+        Some(Value::Number(x)) => serializer.serialize_f64(x),
+        _ => serializer.serialize_str(text),
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a json value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Integer(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(i64::try_from(v)
+            .map(Value::Integer)
+            .unwrap_or(Value::Number(v as f64)))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        let number = Value::Number(v);
+        Ok(number.as_i64_checked().map_or(number, Value::Integer))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut hash_map = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry()? {
+            hash_map.insert(key, value);
+        }
+
+        if hash_map.len() == 1
+            && let Some(Value::String(name)) = hash_map.get(REFERENCE_KEY)
+        {
+            return Ok(Value::Reference(name.clone()));
+        }
+
+        Ok(Value::Object(hash_map))
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_round_trip() {
+        let value = Value::Reference("my_reference".to_string());
+
+        let text = serde_json::to_string(&value).unwrap();
+        assert_eq!(text, r#"{"$ref":"my_reference"}"#);
+        assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), value);
+    }
+
+    #[test]
+    fn deserialize_json() {
+        let text =
+            r#"{"a": [1, -2, 2.0, 2.5, 1e300, 18446744073709551615], "b": {"c": null}, "d": "e"}"#;
+        let value: Value = serde_json::from_str(text).unwrap();
+
+        let expected = Value::Object(HashMap::from_iter(vec![
+            (
+                "a".into(),
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::Integer(-2),
+                    Value::Integer(2),
+                    Value::Number(2.5),
+                    Value::Number(1e300),
+                    Value::Number(u64::MAX as f64),
+                ]),
+            ),
+            (
+                "b".into(),
+                Value::Object(HashMap::from_iter(vec![("c".into(), Value::Null)])),
+            ),
+            ("d".into(), Value::String("e".to_string())),
+        ]));
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let value: Value = r#"{"a": [1, 2.5, true, null, "x\n"], "b": {"c": -3}}"#
+            .parse()
+            .unwrap();
+
+        let text = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), value);
+    }
+
+    #[test]
+    fn value_ref_matches_value() {
+        let source = r#"[1, 2.5, true, null, "x\u0041", {"a": $b}]"#;
+        let value_ref = crate::parser::Parser::from_str(source).to_value().unwrap();
+
+        let from_ref = serde_json::to_value(&value_ref).unwrap();
+        let from_value = serde_json::to_value(value_ref.to_value()).unwrap();
+        assert_eq!(from_ref, from_value);
+        assert_eq!(
+            from_ref,
+            serde_json::json!([1, 2.5, true, null, "xA", {"a": {"$ref": "b"}}])
+        );
+    }
+
+    #[test]
+    fn nested_reference_round_trip() {
+        let value = Value::Array(vec![
+            Value::Integer(1),
+            Value::Object(HashMap::from_iter(vec![(
+                "a".into(),
+                Value::Reference("b".to_string()),
+            )])),
+        ]);
+
+        let text = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), value);
+    }
+
+    #[test]
+    fn raw_numbers() {
+        let raw = |text: &str| serde_json::to_value(Value::RawNumber(text.to_string())).unwrap();
+
+        assert_eq!(raw("1.50"), serde_json::json!(1.5));
+        assert_eq!(raw("0xFF"), serde_json::json!(255));
+        assert_eq!(raw("+1_000"), serde_json::json!(1000));
+        assert_eq!(raw("abc"), serde_json::json!("abc"));
+    }
+}
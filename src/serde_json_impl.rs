@@ -0,0 +1,167 @@
+//! Conversions between [`Value`] and [`serde_json::Value`].
+//!
+//! Integers and floats keep their kind in both directions, a `serde_json` number that does not
+//! fit an `i64` becomes a [`Value::Number`]. `serde_json` can not represent a non-finite float,
+//! so `NaN` and the infinities become `null`.
+//!
+//! `serde_json` has no references either, so a [`Value::Reference`] becomes an object with a
+//! single `"$ref"` entry holding the name, `{"$ref": "name"}`, like the `serde` support writes
+//! it. Such an object is turned back into the `Reference`.
+//!
+//! A [`Value::RawNumber`] becomes the number it spells, extensions like hexadecimal integers
+//! and digit separators included, or a string when it spells no number, like the `serde`
+//! support writes it.
+
+use crate::value::{Value, into_key, parse_raw_number};
+
+const REFERENCE_KEY: &str = "$ref";
+
+impl From<serde_json::Value> for Value {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(x) => Value::Boolean(x),
+            serde_json::Value::Number(x) => match x.as_i64() {
+                Some(x) => Value::Integer(x),
+                None => Value::Number(x.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(x) => Value::String(x),
+            serde_json::Value::Array(values) => {
+                Value::Array(values.into_iter().map(Value::from).collect())
+            }
+            serde_json::Value::Object(map) => match map.get(REFERENCE_KEY) {
+                Some(serde_json::Value::String(name)) if map.len() == 1 => {
+                    Value::Reference(name.clone())
+                }
+                _ => Value::Object(
+                    map.into_iter()
+                        .map(|(k, v)| (into_key(k), Value::from(v)))
+                        .collect(),
+                ),
+            },
+        }
+    }
+}
+
+impl From<Value> for serde_json::Value {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::String(x) => serde_json::Value::String(x),
+            Value::Integer(x) => serde_json::Value::from(x),
+            Value::Number(x) => serde_json::Number::from_f64(x)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            Value::Boolean(x) => serde_json::Value::Bool(x),
+            Value::Array(values) => {
+                serde_json::Value::Array(values.into_iter().map(serde_json::Value::from).collect())
+            }
+            Value::Object(hash_map) => serde_json::Value::Object(
+                hash_map
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), serde_json::Value::from(v)))
+                    .collect(),
+            ),
+            Value::Reference(x) => serde_json::json!({ REFERENCE_KEY: x }),
+            Value::Null => serde_json::Value::Null,
+            Value::RawNumber(x) => match serde_json::from_str(&x) {
+                Ok(number) => serde_json::Value::Number(number),
+                Err(_) => parse_raw_number(&x)
+                    .map_or(serde_json::Value::String(x), serde_json::Value::from),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn round_trip(value: Value, expected: serde_json::Value) {
+        let converted = serde_json::Value::from(value.clone());
+        assert_eq!(converted, expected);
+        assert_eq!(Value::from(converted), value);
+    }
+
+    #[test]
+    fn scalars() {
+        round_trip(Value::Null, json!(null));
+        round_trip(Value::Boolean(true), json!(true));
+        round_trip(Value::Integer(-3), json!(-3));
+        round_trip(Value::Number(2.0), json!(2.0));
+        round_trip(Value::String("a".to_string()), json!("a"));
+    }
+
+    #[test]
+    fn nested_object() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            (
+                "a".into(),
+                Value::Array(vec![Value::Integer(1), Value::Number(1.5)]),
+            ),
+            (
+                "b".into(),
+                Value::Object(HashMap::from_iter(vec![("c".into(), Value::Null)])),
+            ),
+        ]));
+
+        round_trip(value, json!({"a": [1, 1.5], "b": {"c": null}}));
+    }
+
+    #[test]
+    fn references() {
+        round_trip(
+            Value::Reference("name".to_string()),
+            json!({"$ref": "name"}),
+        );
+        round_trip(
+            Value::Array(vec![Value::Reference("a".to_string()), Value::Integer(1)]),
+            json!([{"$ref": "a"}, 1]),
+        );
+
+        assert_eq!(
+            Value::from(json!("$name")),
+            Value::String("$name".to_string())
+        );
+        assert_eq!(
+            Value::from(json!({"$ref": 1})),
+            Value::Object(HashMap::from_iter(vec![("$ref".into(), Value::Integer(1))]))
+        );
+        assert!(matches!(
+            Value::from(json!({"$ref": "a", "b": 1})),
+            Value::Object(map) if map.len() == 2
+        ));
+    }
+
+    #[test]
+    fn lossy_conversions() {
+        assert_eq!(
+            serde_json::Value::from(Value::Number(f64::NAN)),
+            json!(null)
+        );
+        assert_eq!(
+            serde_json::Value::from(Value::RawNumber("1.50".to_string())),
+            json!(1.5)
+        );
+        assert_eq!(
+            serde_json::Value::from(Value::RawNumber("0xFF".to_string())),
+            json!(255)
+        );
+        assert_eq!(
+            serde_json::Value::from(Value::RawNumber("+1_000".to_string())),
+            json!(1000)
+        );
+        assert_eq!(
+            serde_json::Value::from(Value::RawNumber("18446744073709551615".to_string())),
+            json!(u64::MAX)
+        );
+        assert_eq!(
+            serde_json::Value::from(Value::RawNumber("abc".to_string())),
+            json!("abc")
+        );
+        assert_eq!(Value::from(json!(u64::MAX)), Value::Number(u64::MAX as f64));
+    }
+}
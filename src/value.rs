@@ -1,50 +1,2587 @@
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Index,
+    str::FromStr,
+};
 
-#[derive(Debug, PartialEq)]
+use crate::{lexer::Span, parser, parser::Parser, writer::NdjsonWriter};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     String(String),
     Integer(i64),
     Number(f64),
     Boolean(bool),
     Array(Vec<Value>),
-    Object(HashMap<String, Value>),
+    Object(HashMap<Key, Value>),
     Reference(String),
     Null,
+    /// A number as it was written in the source, kept when
+    /// [`ParserConfig::preserve_number_text`](crate::parser::ParserConfig::preserve_number_text)
+    /// is set.
+    RawNumber(String),
+}
+
+/// The key type of [`Value::Object`].
+#[cfg(not(feature = "rc_keys"))]
+pub type Key = String;
+
+/// The key type of [`Value::Object`]. With the `rc_keys` feature identical keys can share one
+/// allocation, see [`Parser::with_key_interning`]. A `Value` is then no longer `Send`.
+#[cfg(feature = "rc_keys")]
+pub type Key = std::rc::Rc<str>;
+
+/// Turn a string into a [`Key`], a `String` key takes it over without a copy.
+#[allow(clippy::useless_conversion)]
+pub(crate) fn into_key(text: String) -> Key {
+    text.into()
 }
 
+const NULL: Value = Value::Null;
+
+/// 2^53, beyond it an `f64` can no longer represent every integer, so a whole number may
+/// already have been rounded.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
 #[derive(Debug, PartialEq)]
-pub enum ValueRef<'a> {
-    String(&'a str),
-    Integer(i64),
-    Number(f64),
-    Boolean(bool),
-    Array(Vec<ValueRef<'a>>),
-    Object(HashMap<&'a str, ValueRef<'a>>),
-    Reference(&'a str),
-    Null,
+pub enum Error {
+    NotAnArray,
+    UnknownVariable(String),
+    KeyCollision(String),
+    /// The JSON Pointer does not resolve to a value.
+    MissingPath(String),
+    /// The value at the JSON Pointer can not be read as the requested type.
+    UnexpectedType(String),
 }
 
-impl<'a> ValueRef<'a> {
-    pub fn to_value(self) -> Value {
+/// Which value is kept when a merge finds two values for the same key that can not be merged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    SelfWins,
+    OtherWins,
+}
+
+/// Why references could not be resolved.
+#[derive(Debug, PartialEq)]
+pub enum ResolveError {
+    /// A reference missing from the context, with the location of the reference in the source
+    /// when it is known.
+    Undefined { name: String, span: Option<Span> },
+    /// References that expand into themselves, from the first name on the cycle back to it,
+    /// like `["a", "b", "a"]`.
+    Cycle(Vec<String>),
+}
+
+impl Value {
+    pub fn object_with_capacity(capacity: usize) -> Value {
+        Value::Object(HashMap::with_capacity(capacity))
+    }
+
+    pub fn array_with_capacity(capacity: usize) -> Value {
+        Value::Array(Vec::with_capacity(capacity))
+    }
+
+    /// View the value as a [`ValueRef`] borrowing all strings, so code written against
+    /// `ValueRef` also works on owned values.
+    pub fn as_value_ref(&self) -> ValueRef<'_> {
         match self {
-            ValueRef::String(x) => Value::String(x.to_string()),
-            ValueRef::Integer(x) => Value::Integer(x),
-            ValueRef::Number(x) => Value::Number(x),
-            ValueRef::Boolean(x) => Value::Boolean(x),
-            ValueRef::Array(value_refs) => Value::Array(
-                value_refs
-                    .into_iter()
-                    .map(|x| ValueRef::to_value(x))
+            Value::String(x) => ValueRef::String(Cow::Borrowed(x)),
+            Value::Integer(x) => ValueRef::Integer(*x),
+            Value::Number(x) => ValueRef::Number(*x),
+            Value::Boolean(x) => ValueRef::Boolean(*x),
+            Value::Array(values) => {
+                ValueRef::Array(values.iter().map(Value::as_value_ref).collect())
+            }
+            Value::Object(hash_map) => ValueRef::Object(
+                hash_map
+                    .iter()
+                    .map(|(k, v)| (Cow::Borrowed(&**k), v.as_value_ref()))
                     .collect(),
             ),
-            ValueRef::Object(hash_map) => Value::Object(
-                hash_map
-                    .into_iter()
-                    .map(|(k, v)| (k.to_string(), ValueRef::to_value(v)))
+            Value::Reference(x) => ValueRef::Reference(x, None),
+            Value::Null => ValueRef::Null,
+            Value::RawNumber(x) => ValueRef::RawNumber(x),
+        }
+    }
+
+    /// Anything that is not an array or an object, references included.
+    pub fn is_scalar(&self) -> bool {
+        !self.is_container()
+    }
+
+    pub fn is_container(&self) -> bool {
+        matches!(self, Value::Array(_) | Value::Object(_))
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, Value::String(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Value::Integer(_))
+    }
+
+    /// Numbers, and raw numbers whether they spell an integer or not.
+    pub fn is_number(&self) -> bool {
+        matches!(self, Value::Number(_) | Value::RawNumber(_))
+    }
+
+    pub fn is_bool(&self) -> bool {
+        matches!(self, Value::Boolean(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, Value::Array(_))
+    }
+
+    pub fn is_object(&self) -> bool {
+        matches!(self, Value::Object(_))
+    }
+
+    pub fn is_reference(&self) -> bool {
+        matches!(self, Value::Reference(_))
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Only integers, see [`Value::as_i64_checked`] to also accept numbers without a fraction.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(x) => Some(*x),
+            Value::RawNumber(x) => parse_raw_number(x)?.as_i64(),
+            _ => None,
+        }
+    }
+
+    /// Numbers, and integers cast to `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_f64_lossy()
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&HashMap<Key, Value>> {
+        match self {
+            Value::Object(hash_map) => Some(hash_map),
+            _ => None,
+        }
+    }
+
+    /// The value of `key` when this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(hash_map) => hash_map.get(key),
+            _ => None,
+        }
+    }
+
+    /// The element at `index` when this is an array that is long enough.
+    pub fn get_index(&self, index: usize) -> Option<&Value> {
+        match self {
+            Value::Array(values) => values.get(index),
+            _ => None,
+        }
+    }
+
+    pub fn object_keys(&self) -> Option<Vec<&str>> {
+        match self {
+            Value::Object(hash_map) => Some(hash_map.keys().map(|k| &**k).collect()),
+            _ => None,
+        }
+    }
+
+    pub fn array_len(&self) -> Option<usize> {
+        match self {
+            Value::Array(values) => Some(values.len()),
+            _ => None,
+        }
+    }
+
+    pub fn first(&self) -> Option<&Value> {
+        match self {
+            Value::Array(values) => values.first(),
+            _ => None,
+        }
+    }
+
+    pub fn last(&self) -> Option<&Value> {
+        match self {
+            Value::Array(values) => values.last(),
+            _ => None,
+        }
+    }
+
+    /// Split an array into the elements matching `pred` and the other elements, `None` for
+    /// non-arrays.
+    pub fn partition_array<F: Fn(&Value) -> bool>(&self, pred: F) -> Option<(Value, Value)> {
+        match self {
+            Value::Array(values) => {
+                let (matching, other) = values.iter().cloned().partition(|v| pred(v));
+                Some((Value::Array(matching), Value::Array(other)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Remove repeated elements from an array, keeping the first occurrence of each.
+    ///
+    /// Elements are compared with `PartialEq`, so `NaN` numbers are never seen as duplicates.
+    pub fn array_dedup(&mut self) {
+        if let Value::Array(values) = self {
+            let mut unique: Vec<Value> = Vec::with_capacity(values.len());
+            for value in values.drain(..) {
+                if !unique.contains(&value) {
+                    unique.push(value);
+                }
+            }
+            *values = unique;
+        }
+    }
+
+    /// Flatten an array of arrays one level, `[[1, 2], [3]]` becomes `[1, 2, 3]`.
+    ///
+    /// Only applies when every element is an array, any other value is left untouched.
+    pub fn flatten_one(&mut self) {
+        if let Value::Array(values) = self
+            && values.iter().all(|v| matches!(v, Value::Array(_)))
+        {
+            *values = values
+                .drain(..)
+                .flat_map(|v| match v {
+                    Value::Array(inner) => inner,
+                    _ => unreachable!("checked that every element is an array"),
+                })
+                .collect();
+        }
+    }
+
+    /// Counts the scalar values in the tree, arrays and objects themselves are not counted.
+    pub fn count_leaves(&self) -> usize {
+        match self {
+            Value::Array(values) => values.iter().map(Value::count_leaves).sum(),
+            Value::Object(hash_map) => hash_map.values().map(Value::count_leaves).sum(),
+            _ => 1,
+        }
+    }
+
+    /// The length of the longest array anywhere in the tree, `0` when there are no arrays.
+    pub fn max_array_len(&self) -> usize {
+        match self {
+            Value::Array(values) => values
+                .iter()
+                .map(Value::max_array_len)
+                .fold(values.len(), usize::max),
+            Value::Object(hash_map) => hash_map
+                .values()
+                .map(Value::max_array_len)
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Read an object of strings, like headers or labels, as borrowed pairs.
+    ///
+    /// Returns `None` when the value is not an object or any of its values is not a string.
+    pub fn as_string_map(&self) -> Option<HashMap<&str, &str>> {
+        match self {
+            Value::Object(hash_map) => hash_map
+                .iter()
+                .map(|(k, v)| match v {
+                    Value::String(x) => Some((&**k, x.as_str())),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+
+    /// Read a tagged object like `{"type": "A", "value": ...}` as its tag and content.
+    ///
+    /// The object must contain a string under `tag_key` and at most one other entry, which is
+    /// the content whatever its key. An object with only the tag has `Value::Null` as content.
+    pub fn as_tagged(&self, tag_key: &str) -> Option<(&str, &Value)> {
+        let Value::Object(hash_map) = self else {
+            return None;
+        };
+        let Some(Value::String(tag)) = hash_map.get(tag_key) else {
+            return None;
+        };
+
+        let mut rest = hash_map
+            .iter()
+            .filter(|(k, _)| &***k != tag_key)
+            .map(|(_, v)| v);
+        match (rest.next(), rest.next()) {
+            (None, _) => Some((tag, &NULL)),
+            (Some(content), None) => Some((tag, content)),
+            _ => None,
+        }
+    }
+
+    /// Render a scalar as a plain string, strings are returned without quotes. Returns `None`
+    /// for arrays, objects and references.
+    pub fn to_scalar_string(&self) -> Option<String> {
+        match self {
+            Value::String(x) => Some(x.clone()),
+            Value::Integer(x) => Some(x.to_string()),
+            Value::Number(x) => Some(x.to_string()),
+            Value::Boolean(x) => Some(x.to_string()),
+            Value::Null => Some("null".to_string()),
+            Value::RawNumber(x) => Some(x.clone()),
+            Value::Array(_) | Value::Object(_) | Value::Reference(_) => None,
+        }
+    }
+
+    /// Read the value as an `i128`, accepting integers, integral numbers and strings containing
+    /// an integer. Useful for ids that don't fit in an `i64`, like 64-bit unsigned ids.
+    ///
+    /// Numbers beyond 2^53 are rejected, they may have been rounded when they were parsed. Keep
+    /// such ids exact as strings or with
+    /// [`ParserConfig::preserve_number_text`](crate::parser::ParserConfig::preserve_number_text).
+    pub fn as_i128(&self) -> Option<i128> {
+        match self {
+            Value::Integer(x) => Some(i128::from(*x)),
+            Value::Number(x) if x.fract() == 0.0 && x.abs() <= MAX_SAFE_INTEGER => Some(*x as i128),
+            Value::String(x) => x.parse().ok(),
+            Value::RawNumber(x) => x.parse().ok().or_else(|| parse_raw_number(x)?.as_i128()),
+            _ => None,
+        }
+    }
+
+    /// The names of all references in the tree.
+    pub fn references(&self) -> HashSet<&str> {
+        let mut names = HashSet::new();
+        self.visit_references(&mut |name| {
+            names.insert(name);
+        });
+        names
+    }
+
+    /// Lists the names of references in the tree that are missing from `table`, sorted and
+    /// without duplicates.
+    pub fn unresolved_references(&self, table: &HashMap<String, Value>) -> Vec<String> {
+        let mut names = Vec::new();
+        self.visit_references(&mut |name| {
+            if !table.contains_key(name) {
+                names.push(name.to_string());
+            }
+        });
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Copy the tree, replacing every reference by its value in `context`, with the
+    /// references in that value resolved as well.
+    ///
+    /// An owned value no longer knows where it was parsed from, so the error for a missing
+    /// reference has no span, see [`ValueRef::resolve_references`] for that.
+    pub fn resolve_references(
+        &self,
+        context: &HashMap<String, Value>,
+    ) -> Result<Value, ResolveError> {
+        Resolver::new(context).resolve(self)
+    }
+
+    /// Replace every reference found in `context` by a copy of its value and leave the others
+    /// in place, returning the names that are still unresolved.
+    ///
+    /// The copied values are not resolved again, so a table can be built up over multiple
+    /// passes.
+    pub fn try_resolve_partial(&mut self, context: &HashMap<String, Value>) -> HashSet<String> {
+        let mut unresolved = HashSet::new();
+        self.resolve_partial_inner(context, &mut unresolved);
+        unresolved
+    }
+
+    fn resolve_partial_inner(
+        &mut self,
+        context: &HashMap<String, Value>,
+        unresolved: &mut HashSet<String>,
+    ) {
+        match self {
+            Value::Reference(name) => match context.get(name) {
+                Some(value) => *self = value.clone(),
+                None => {
+                    unresolved.insert(name.clone());
+                }
+            },
+            Value::Array(values) => values
+                .iter_mut()
+                .for_each(|v| v.resolve_partial_inner(context, unresolved)),
+            Value::Object(hash_map) => hash_map
+                .values_mut()
+                .for_each(|v| v.resolve_partial_inner(context, unresolved)),
+            _ => {}
+        }
+    }
+
+    fn visit_references<'a>(&'a self, f: &mut impl FnMut(&'a str)) {
+        match self {
+            Value::Reference(name) => f(name),
+            Value::Array(values) => values.iter().for_each(|v| v.visit_references(f)),
+            Value::Object(hash_map) => hash_map.values().for_each(|v| v.visit_references(f)),
+            _ => {}
+        }
+    }
+
+    /// Look up a value by JSON Pointer (RFC 6901), like `/foo/0/bar`.
+    ///
+    /// Returns `None` when the pointer is malformed or doesn't resolve.
+    pub fn pointer(&self, ptr: &str) -> Option<&Value> {
+        parse_pointer(ptr)?
+            .into_iter()
+            .try_fold(self, |value, token| match value {
+                Value::Object(hash_map) => hash_map.get(token.as_ref()),
+                Value::Array(values) => values.get(parse_pointer_index(&token)?),
+                _ => None,
+            })
+    }
+
+    /// Like [`Value::pointer`], but gives a mutable reference for editing in place.
+    pub fn pointer_mut(&mut self, ptr: &str) -> Option<&mut Value> {
+        parse_pointer(ptr)?
+            .into_iter()
+            .try_fold(self, |value, token| match value {
+                Value::Object(hash_map) => hash_map.get_mut(token.as_ref()),
+                Value::Array(values) => values.get_mut(parse_pointer_index(&token)?),
+                _ => None,
+            })
+    }
+
+    /// Whether the JSON Pointer `ptr` resolves to a value, a malformed pointer never does.
+    pub fn path_exists(&self, ptr: &str) -> bool {
+        self.pointer(ptr).is_some()
+    }
+
+    /// Read the value at the JSON Pointer `ptr` as a `T`, for example a port from a config.
+    ///
+    /// Returns [`Error::MissingPath`] when nothing is at `ptr` and [`Error::UnexpectedType`]
+    /// when the value there is not a `T`.
+    pub fn get_as<T: FromValue>(&self, ptr: &str) -> Result<T, Error> {
+        let value = self
+            .pointer(ptr)
+            .ok_or_else(|| Error::MissingPath(ptr.to_string()))?;
+        T::from_value(value).ok_or_else(|| Error::UnexpectedType(ptr.to_string()))
+    }
+
+    /// Select all values matching a small path query.
+    ///
+    /// The query is a list of segments separated by `.`, where each segment is an optional
+    /// key followed by any number of bracketed selectors:
+    ///
+    /// ```text
+    /// query    = [ segment *( "." segment ) ]
+    /// segment  = ( key / "*" ) *selector / 1*selector
+    /// selector = "[" ( index / slice / "*" ) "]"
+    /// slice    = [ index ] ":" [ index ]
+    /// key      = 1*( any character except "." "[" "]" )
+    /// index    = 1*DIGIT
+    /// ```
+    ///
+    /// A key selects that key of an object, `*` selects every value of an object or array, an
+    /// index selects one array element and a slice the elements from `start` up to (excluding)
+    /// `end`. So `a.*.b` and `items[0:2].name` are valid queries. Selectors that don't apply to
+    /// a value simply don't match, an empty query selects `self` and an invalid query selects
+    /// nothing.
+    pub fn select(&self, query: &str) -> Vec<&Value> {
+        let Some(selectors) = parse_query(query) else {
+            return Vec::new();
+        };
+
+        let mut current = vec![self];
+        for selector in selectors {
+            current = current
+                .into_iter()
+                .flat_map(|value| selector.apply(value))
+                .collect();
+        }
+        current
+    }
+
+    /// Write every element of an array as a line of compact JSON (NDJSON), see
+    /// [`NdjsonWriter`] for other record separators.
+    pub fn to_ndjson(&self) -> Result<String, Error> {
+        NdjsonWriter::new().write(self)
+    }
+
+    /// Replace `${name}` placeholders in every string value with the matching variable.
+    ///
+    /// Unknown placeholders are left as is, unless `strict` is set, then they return
+    /// [`Error::UnknownVariable`]. Strings visited before the error have already been replaced.
+    pub fn interpolate(
+        &mut self,
+        vars: &HashMap<String, String>,
+        strict: bool,
+    ) -> Result<(), Error> {
+        match self {
+            Value::String(x) => {
+                if let Some(replaced) = interpolate_str(x, vars, strict)? {
+                    *x = replaced;
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    value.interpolate(vars, strict)?;
+                }
+            }
+            Value::Object(hash_map) => {
+                for value in hash_map.values_mut() {
+                    value.interpolate(vars, strict)?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Replace every object key in the tree by `f(key)`, for example to turn keys into valid
+    /// identifiers.
+    ///
+    /// When two keys of the same object map to the same new key [`Error::KeyCollision`] is
+    /// returned with that key and the object is left untouched. Objects visited before the error
+    /// have already been changed.
+    pub fn sanitize_keys(&mut self, mut f: impl FnMut(&str) -> String) -> Result<(), Error> {
+        self.sanitize_keys_inner(&mut f)
+    }
+
+    fn sanitize_keys_inner(&mut self, f: &mut impl FnMut(&str) -> String) -> Result<(), Error> {
+        match self {
+            Value::Array(values) => {
+                for value in values {
+                    value.sanitize_keys_inner(f)?;
+                }
+            }
+            Value::Object(hash_map) => {
+                let mut sanitized = HashMap::with_capacity(hash_map.len());
+                for key in hash_map.keys() {
+                    let new_key = f(key);
+                    if sanitized.insert(new_key.clone(), key.clone()).is_some() {
+                        return Err(Error::KeyCollision(new_key));
+                    }
+                }
+
+                let mut old = std::mem::take(hash_map);
+                let mut result = Ok(());
+                for (new_key, key) in sanitized {
+                    let mut value = old.remove(&key).unwrap_or(Value::Null);
+                    if result.is_ok() {
+                        result = value.sanitize_keys_inner(f);
+                    }
+                    hash_map.insert(into_key(new_key), value);
+                }
+                result?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Whether both documents have the same keys, array lengths and leaf types, ignoring the
+    /// scalar values themselves.
+    pub fn same_shape(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Array(left), Value::Array(right)) => {
+                left.len() == right.len() && left.iter().zip(right).all(|(l, r)| l.same_shape(r))
+            }
+            (Value::Object(left), Value::Object(right)) => {
+                left.len() == right.len()
+                    && left
+                        .iter()
+                        .all(|(k, l)| right.get(k).is_some_and(|r| l.same_shape(r)))
+            }
+            (left, right) => std::mem::discriminant(left) == std::mem::discriminant(right),
+        }
+    }
+
+    /// Like `==`, but `NaN` numbers are equal to each other.
+    ///
+    /// The derived `PartialEq` follows `f64`, so a value containing `NaN` is never equal to
+    /// anything, not even to itself. Objects compare equal regardless of key order either way.
+    pub fn eq_nan_aware(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_nan_aware(b))
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(k, a)| b.get(k).is_some_and(|b| a.eq_nan_aware(b)))
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Read an integer or number as an `f64`. Integers beyond 2^53 may lose precision.
+    pub fn as_f64_lossy(&self) -> Option<f64> {
+        match self {
+            Value::Integer(x) => Some(*x as f64),
+            Value::Number(x) => Some(*x),
+            Value::RawNumber(x) => parse_raw_number(x)?.as_f64_lossy(),
+            _ => None,
+        }
+    }
+
+    /// Read an integer, or a number without a fractional part that fits in an `i64`. Numbers
+    /// are never truncated or saturated.
+    pub fn as_i64_checked(&self) -> Option<i64> {
+        match self {
+            Value::Integer(x) => Some(*x),
+            Value::Number(x)
+                if x.fract() == 0.0 && *x >= i64::MIN as f64 && *x < i64::MAX as f64 =>
+            {
+                Some(*x as i64)
+            }
+            Value::RawNumber(x) => parse_raw_number(x)?.as_i64_checked(),
+            _ => None,
+        }
+    }
+
+    /// Keep only the listed keys in every object of the tree.
+    pub fn retain_keys(&mut self, keys: &[&str]) {
+        self.filter_keys(&|key| keys.contains(&key));
+    }
+
+    /// Remove the listed keys from every object of the tree.
+    pub fn remove_keys(&mut self, keys: &[&str]) {
+        self.filter_keys(&|key| !keys.contains(&key));
+    }
+
+    fn filter_keys(&mut self, keep: &impl Fn(&str) -> bool) {
+        match self {
+            Value::Array(values) => values.iter_mut().for_each(|v| v.filter_keys(keep)),
+            Value::Object(hash_map) => {
+                hash_map.retain(|k, _| keep(k));
+                hash_map.values_mut().for_each(|v| v.filter_keys(keep));
+            }
+            _ => {}
+        }
+    }
+
+    /// Keep only the paths that exist in both `self` and `other`, taking the values from `self`.
+    ///
+    /// Objects are intersected by key and arrays by index. When the two sides are not both
+    /// objects or both arrays the value of `self` is kept as is.
+    pub fn intersect(&self, other: &Value) -> Value {
+        match (self, other) {
+            (Value::Object(left), Value::Object(right)) => Value::Object(
+                left.iter()
+                    .filter_map(|(k, v)| right.get(k).map(|other| (k.clone(), v.intersect(other))))
                     .collect(),
             ),
-            ValueRef::Reference(x) => Value::Reference(x.to_string()),
-            ValueRef::Null => Value::Null,
+            (Value::Array(left), Value::Array(right)) => Value::Array(
+                left.iter()
+                    .zip(right)
+                    .map(|(v, other)| v.intersect(other))
+                    .collect(),
+            ),
+            _ => self.clone(),
+        }
+    }
+
+    /// Merge `other` into `self`. Objects are merged recursively, any other value in `other`
+    /// replaces the one in `self`.
+    pub fn deep_merge(&mut self, other: Value) {
+        self.merge_preferring(other, Side::OtherWins)
+    }
+
+    /// Merge `other` into `self`, objects are merged recursively and `prefer` decides which
+    /// value is kept for any other conflict.
+    pub fn merge_preferring(&mut self, other: Value, prefer: Side) {
+        match (self, other) {
+            (Value::Object(left), Value::Object(right)) => {
+                for (k, v) in right {
+                    match left.get_mut(&k) {
+                        Some(existing) => existing.merge_preferring(v, prefer),
+                        None => {
+                            left.insert(k, v);
+                        }
+                    }
+                }
+            }
+            (this, other) => {
+                if prefer == Side::OtherWins {
+                    *this = other
+                }
+            }
+        }
+    }
+
+    /// Fold all documents into one using [`Value::deep_merge`], later documents win.
+    ///
+    /// Returns `Value::Null` when `docs` is empty.
+    pub fn deep_merge_all<I: IntoIterator<Item = Value>>(docs: I) -> Value {
+        let mut docs = docs.into_iter();
+        let mut result = docs.next().unwrap_or(Value::Null);
+        for doc in docs {
+            result.deep_merge(doc);
         }
+        result
+    }
+}
+
+/// Parse a document into an owned value.
+///
+/// ```
+/// use json_outliner::value::Value;
+///
+/// let value: Value = "[1,2,3]".parse().unwrap();
+/// assert_eq!(value.array_len(), Some(3));
+/// ```
+impl FromStr for Value {
+    type Err = parser::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::from_str(s).to_value().map(ValueRef::to_value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Integer(value.into())
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::Integer(value.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Value::Array(value)
+    }
+}
+
+impl From<HashMap<Key, Value>> for Value {
+    fn from(value: HashMap<Key, Value>) -> Self {
+        Value::Object(value)
+    }
+}
+
+/// `None` becomes `Value::Null`.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// `value["users"]`, never panics.
+///
+/// Gives a shared `Value::Null` when this is not an object or the key is missing, so lookups
+/// can be chained like `value["users"][0]["name"]`. Use [`Value::get`] to tell a missing key
+/// apart from an explicit `null`.
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// `value[0]`, never panics.
+///
+/// Gives a shared `Value::Null` when this is not an array or the index is out of bounds. Use
+/// [`Value::get_index`] to tell a missing element apart from an explicit `null`.
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        self.get_index(index).unwrap_or(&NULL)
+    }
+}
+
+impl PartialEq<str> for Value {
+    fn eq(&self, other: &str) -> bool {
+        matches!(self, Value::String(x) if x == other)
+    }
+}
+
+impl PartialEq<&str> for Value {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<i64> for Value {
+    fn eq(&self, other: &i64) -> bool {
+        match self {
+            Value::Integer(x) => x == other,
+            Value::RawNumber(x) => parse_raw_number(x).is_some_and(|x| x == *other),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        match self {
+            Value::Number(x) => x == other,
+            Value::RawNumber(x) => parse_raw_number(x).is_some_and(|x| x == *other),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<bool> for Value {
+    fn eq(&self, other: &bool) -> bool {
+        matches!(self, Value::Boolean(x) if x == other)
+    }
+}
+
+/// Typed extraction from a [`Value`], see [`Value::get_as`].
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_i64_checked()
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_f64_lossy()
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_str().map(str::to_string)
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ValueRef<'a> {
+    /// The string with its escape sequences decoded, borrowing from the source when there was
+    /// nothing to decode.
+    String(Cow<'a, str>),
+    Integer(i64),
+    Number(f64),
+    Boolean(bool),
+    Array(Vec<ValueRef<'a>>),
+    /// Keys have their escape sequences decoded like strings.
+    Object(HashMap<Cow<'a, str>, ValueRef<'a>>),
+    /// The name without its `$` sigil, and the span of the reference token when it was
+    /// parsed from text.
+    Reference(&'a str, Option<Span>),
+    Null,
+    /// A number as it was written in the source, see [`Value::RawNumber`].
+    RawNumber(&'a str),
+}
+
+impl<'a> ValueRef<'a> {
+    /// Returns the decoded string, `None` for non-strings.
+    pub fn cow_string(&self) -> Option<Cow<'a, str>> {
+        match self {
+            ValueRef::String(x) => Some(x.clone()),
+            _ => None,
+        }
+    }
+
+    /// Convert to an owned [`Value`], replacing every reference by its value in `context`,
+    /// with the references in that value resolved as well.
+    ///
+    /// The error for a missing reference carries the span of the reference token, so it can
+    /// point at the reference in the source.
+    pub fn resolve_references(
+        self,
+        context: &HashMap<String, Value>,
+    ) -> Result<Value, ResolveError> {
+        self.resolve_with(&mut Resolver::new(context))
+    }
+
+    fn resolve_with(self, resolver: &mut Resolver) -> Result<Value, ResolveError> {
+        match self {
+            ValueRef::Reference(name, span) => resolver.expand(name, span),
+            ValueRef::Array(value_refs) => value_refs
+                .into_iter()
+                .map(|v| v.resolve_with(resolver))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            ValueRef::Object(hash_map) => hash_map
+                .into_iter()
+                .map(|(k, v)| Ok((k.into(), v.resolve_with(resolver)?)))
+                .collect::<Result<_, _>>()
+                .map(Value::Object),
+            other => Ok(other.to_value()),
+        }
+    }
+
+    /// The names of all references in the tree, borrowed from the source.
+    pub fn references(&self) -> HashSet<&'a str> {
+        let mut names = HashSet::new();
+        self.visit_references(&mut |name| {
+            names.insert(name);
+        });
+        names
+    }
+
+    fn visit_references(&self, f: &mut impl FnMut(&'a str)) {
+        match self {
+            ValueRef::Reference(name, _) => f(name),
+            ValueRef::Array(value_refs) => value_refs.iter().for_each(|v| v.visit_references(f)),
+            ValueRef::Object(hash_map) => hash_map.values().for_each(|v| v.visit_references(f)),
+            _ => {}
+        }
+    }
+
+    pub fn to_value(self) -> Value {
+        self.into_value_with(&mut Key::from)
+    }
+
+    /// Like [`ValueRef::to_value`], but identical keys share one allocation, the one kept in
+    /// `keys`.
+    #[cfg(feature = "rc_keys")]
+    pub(crate) fn into_value_interned(self, keys: &mut HashSet<Key>) -> Value {
+        self.into_value_with(&mut |key| match keys.get(&*key) {
+            Some(interned) => interned.clone(),
+            None => {
+                let key = Key::from(key);
+                keys.insert(key.clone());
+                key
+            }
+        })
+    }
+
+    fn into_value_with(self, key: &mut impl FnMut(Cow<'a, str>) -> Key) -> Value {
+        match self {
+            ValueRef::String(x) => Value::String(x.into_owned()),
+            ValueRef::Integer(x) => Value::Integer(x),
+            ValueRef::Number(x) => Value::Number(x),
+            ValueRef::Boolean(x) => Value::Boolean(x),
+            ValueRef::Array(value_refs) => Value::Array(
+                value_refs
+                    .into_iter()
+                    .map(|v| v.into_value_with(key))
+                    .collect(),
+            ),
+            ValueRef::Object(hash_map) => Value::Object(
+                hash_map
+                    .into_iter()
+                    .map(|(k, v)| (key(k), v.into_value_with(key)))
+                    .collect(),
+            ),
+            ValueRef::Reference(x, _) => Value::Reference(x.to_string()),
+            ValueRef::Null => Value::Null,
+            ValueRef::RawNumber(x) => Value::RawNumber(x.to_string()),
+        }
+    }
+}
+
+/// Resolves the values in a context in dependency order.
+///
+/// A name is resolved the first time it is referenced, after the names its value depends on,
+/// and kept so later references to it are a copy instead of another expansion.
+struct Resolver<'c> {
+    context: &'c HashMap<String, Value>,
+    /// The names whose values are being resolved, outermost first.
+    expanding: Vec<&'c str>,
+    resolved: HashMap<&'c str, Value>,
+}
+
+impl<'c> Resolver<'c> {
+    fn new(context: &'c HashMap<String, Value>) -> Self {
+        Resolver {
+            context,
+            expanding: Vec::new(),
+            resolved: HashMap::new(),
+        }
+    }
+
+    fn resolve(&mut self, value: &Value) -> Result<Value, ResolveError> {
+        match value {
+            Value::Reference(name) => self.expand(name, None),
+            Value::Array(values) => values
+                .iter()
+                .map(|v| self.resolve(v))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            Value::Object(hash_map) => hash_map
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), self.resolve(v)?)))
+                .collect::<Result<_, _>>()
+                .map(Value::Object),
+            other => Ok(other.clone()),
+        }
+    }
+
+    fn expand(&mut self, name: &str, span: Option<Span>) -> Result<Value, ResolveError> {
+        if let Some(value) = self.resolved.get(name) {
+            return Ok(value.clone());
+        }
+        if let Some(start) = self.expanding.iter().position(|n| *n == name) {
+            let mut cycle: Vec<String> = self.expanding[start..]
+                .iter()
+                .map(|n| n.to_string())
+                .collect();
+            cycle.push(name.to_string());
+            return Err(ResolveError::Cycle(cycle));
+        }
+
+        let (name, value) =
+            self.context
+                .get_key_value(name)
+                .ok_or_else(|| ResolveError::Undefined {
+                    name: name.to_string(),
+                    span,
+                })?;
+        self.expanding.push(name);
+        let resolved = self.resolve(value);
+        self.expanding.pop();
+
+        let resolved = resolved?;
+        self.resolved.insert(name, resolved.clone());
+        Ok(resolved)
+    }
+}
+
+/// The [`Value::Integer`] or [`Value::Number`] the text of a raw number spells. Every number
+/// extension is allowed, the text was already checked against the config it was parsed with.
+pub(crate) fn parse_raw_number(text: &str) -> Option<Value> {
+    let config = parser::ParserConfig {
+        allow_hex: true,
+        allow_plus_sign: true,
+        allow_leading_zeros: true,
+        allow_bare_decimal_point: true,
+        allow_non_finite: true,
+        allow_digit_separators: true,
+        ..Default::default()
+    };
+    match Parser::from_str(text).with_config(config).to_value().ok()? {
+        value @ (ValueRef::Integer(_) | ValueRef::Number(_)) => Some(value.to_value()),
+        _ => None,
+    }
+}
+
+fn interpolate_str(
+    text: &str,
+    vars: &HashMap<String, String>,
+    strict: bool,
+) -> Result<Option<String>, Error> {
+    if !text.contains("${") {
+        return Ok(None);
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + end];
+        out.push_str(&rest[..start]);
+        match vars.get(name) {
+            Some(var) => out.push_str(var),
+            None if strict => return Err(Error::UnknownVariable(name.to_string())),
+            None => out.push_str(&rest[start..=start + end]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+
+    Ok(Some(out))
+}
+
+/// Splits a JSON Pointer into its unescaped reference tokens, `None` when it is malformed.
+fn parse_pointer(ptr: &str) -> Option<Vec<Cow<'_, str>>> {
+    if ptr.is_empty() {
+        return Some(Vec::new());
+    }
+
+    ptr.strip_prefix('/')?
+        .split('/')
+        .map(unescape_pointer_token)
+        .collect()
+}
+
+fn unescape_pointer_token(token: &str) -> Option<Cow<'_, str>> {
+    if !token.contains('~') {
+        return Some(Cow::Borrowed(token));
+    }
+
+    let mut out = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '~' => match chars.next()? {
+                '0' => out.push('~'),
+                '1' => out.push('/'),
+                _ => return None,
+            },
+            ch => out.push(ch),
+        }
+    }
+
+    Some(Cow::Owned(out))
+}
+
+/// Array indices are decimal without leading zeros, `-` (past the end) never resolves.
+fn parse_pointer_index(token: &str) -> Option<usize> {
+    match token.as_bytes() {
+        [b'0'] => Some(0),
+        [b'1'..=b'9', rest @ ..] if rest.iter().all(u8::is_ascii_digit) => token.parse().ok(),
+        _ => None,
+    }
+}
+
+enum Selector<'a> {
+    Key(&'a str),
+    Wildcard,
+    Index(usize),
+    Slice(Option<usize>, Option<usize>),
+}
+
+impl<'a> Selector<'a> {
+    fn apply<'v>(&self, value: &'v Value) -> Vec<&'v Value> {
+        match (self, value) {
+            (Selector::Key(key), Value::Object(hash_map)) => {
+                hash_map.get(*key).into_iter().collect()
+            }
+            (Selector::Wildcard, Value::Object(hash_map)) => hash_map.values().collect(),
+            (Selector::Wildcard, Value::Array(values)) => values.iter().collect(),
+            (Selector::Index(index), Value::Array(values)) => {
+                values.get(*index).into_iter().collect()
+            }
+            (Selector::Slice(start, end), Value::Array(values)) => {
+                let end = end.unwrap_or(values.len()).min(values.len());
+                let start = start.unwrap_or(0).min(end);
+                values[start..end].iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn parse_query(query: &str) -> Option<Vec<Selector<'_>>> {
+    let mut selectors = Vec::new();
+    if query.is_empty() {
+        return Some(selectors);
+    }
+
+    for segment in query.split('.') {
+        let (key, mut rest) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+        match key {
+            "" if rest.is_empty() => return None,
+            "" => {}
+            "*" => selectors.push(Selector::Wildcard),
+            key if key.contains(']') => return None,
+            key => selectors.push(Selector::Key(key)),
+        }
+
+        while !rest.is_empty() {
+            let end = rest.find(']')?;
+            let inner = rest.strip_prefix('[')?.get(..end - 1)?;
+            selectors.push(parse_selector(inner)?);
+            rest = &rest[end + 1..];
+        }
+    }
+
+    Some(selectors)
+}
+
+fn parse_selector(inner: &str) -> Option<Selector<'_>> {
+    fn index(text: &str) -> Option<Option<usize>> {
+        match text {
+            "" => Some(None),
+            text if text.bytes().all(|b| b.is_ascii_digit()) => text.parse().ok().map(Some),
+            _ => None,
+        }
+    }
+
+    match inner.split_once(':') {
+        _ if inner == "*" => Some(Selector::Wildcard),
+        Some((start, end)) => Some(Selector::Slice(index(start)?, index(end)?)),
+        None => index(inner)?.map(Selector::Index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::LineIndex;
+
+    #[test]
+    fn object_keys() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Integer(1)),
+            ("b".into(), Value::Null),
+        ]));
+
+        let mut keys = value.object_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(value.array_len(), None);
+    }
+
+    #[test]
+    fn array_len() {
+        let value = Value::Array(vec![Value::Integer(1), Value::Boolean(true)]);
+
+        assert_eq!(value.array_len(), Some(2));
+        assert_eq!(value.object_keys(), None);
+    }
+
+    #[test]
+    fn intersect_objects() {
+        let left = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Integer(1)),
+            ("b".into(), Value::Boolean(true)),
+            (
+                "c".into(),
+                Value::Object(HashMap::from_iter(vec![
+                    ("d".into(), Value::Null),
+                    ("e".into(), Value::Integer(2)),
+                ])),
+            ),
+            (
+                "f".into(),
+                Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+            ),
+        ]));
+        let right = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Integer(10)),
+            (
+                "c".into(),
+                Value::Object(HashMap::from_iter(vec![(
+                    "e".into(),
+                    Value::String("other".to_string()),
+                )])),
+            ),
+            ("f".into(), Value::Array(vec![Value::Null])),
+            ("g".into(), Value::Null),
+        ]));
+
+        let expected = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Integer(1)),
+            (
+                "c".into(),
+                Value::Object(HashMap::from_iter(vec![("e".into(), Value::Integer(2))])),
+            ),
+            ("f".into(), Value::Array(vec![Value::Integer(1)])),
+        ]));
+
+        assert_eq!(left.intersect(&right), expected);
+    }
+
+    #[test]
+    fn deep_merge_all_layers() {
+        let base = Value::Object(HashMap::from_iter(vec![
+            ("name".into(), Value::String("base".to_string())),
+            (
+                "server".into(),
+                Value::Object(HashMap::from_iter(vec![
+                    ("host".into(), Value::String("localhost".to_string())),
+                    ("port".into(), Value::Integer(80)),
+                ])),
+            ),
+        ]));
+        let env = Value::Object(HashMap::from_iter(vec![(
+            "server".into(),
+            Value::Object(HashMap::from_iter(vec![(
+                "port".into(),
+                Value::Integer(8080),
+            )])),
+        )]));
+        let local = Value::Object(HashMap::from_iter(vec![
+            ("name".into(), Value::String("local".to_string())),
+            ("debug".into(), Value::Boolean(true)),
+        ]));
+
+        let expected = Value::Object(HashMap::from_iter(vec![
+            ("name".into(), Value::String("local".to_string())),
+            ("debug".into(), Value::Boolean(true)),
+            (
+                "server".into(),
+                Value::Object(HashMap::from_iter(vec![
+                    ("host".into(), Value::String("localhost".to_string())),
+                    ("port".into(), Value::Integer(8080)),
+                ])),
+            ),
+        ]));
+
+        assert_eq!(Value::deep_merge_all(vec![base, env, local]), expected);
+        assert_eq!(Value::deep_merge_all(Vec::new()), Value::Null);
+    }
+
+    #[test]
+    fn merge_preferring_conflicting_scalars() {
+        let left = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Integer(1)),
+            (
+                "nested".into(),
+                Value::Object(HashMap::from_iter(vec![("b".into(), Value::Boolean(true))])),
+            ),
+        ]));
+        let right = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Integer(2)),
+            ("c".into(), Value::Null),
+            (
+                "nested".into(),
+                Value::Object(HashMap::from_iter(vec![(
+                    "b".into(),
+                    Value::Boolean(false),
+                )])),
+            ),
+        ]));
+
+        let mut self_wins = left.clone();
+        self_wins.merge_preferring(right.clone(), Side::SelfWins);
+        assert_eq!(self_wins.pointer("/a"), Some(&Value::Integer(1)));
+        assert_eq!(self_wins.pointer("/nested/b"), Some(&Value::Boolean(true)));
+        assert_eq!(self_wins.pointer("/c"), Some(&Value::Null));
+
+        let mut other_wins = left;
+        other_wins.merge_preferring(right, Side::OtherWins);
+        assert_eq!(other_wins.pointer("/a"), Some(&Value::Integer(2)));
+        assert_eq!(
+            other_wins.pointer("/nested/b"),
+            Some(&Value::Boolean(false))
+        );
+        assert_eq!(other_wins.pointer("/c"), Some(&Value::Null));
+    }
+
+    #[test]
+    fn as_i128_large_integer() {
+        assert_eq!(
+            Value::String("18446744073709551615".to_string()).as_i128(),
+            Some(u64::MAX as i128)
+        );
+        assert_eq!(
+            Value::RawNumber("18446744073709551615".to_string()).as_i128(),
+            Some(u64::MAX as i128)
+        );
+        assert_eq!(Value::Integer(i64::MAX).as_i128(), Some(i64::MAX as i128));
+        assert_eq!(Value::Number(-1e15).as_i128(), Some(-1_000_000_000_000_000));
+    }
+
+    #[test]
+    fn as_i128_rounded_number() {
+        assert_eq!(Value::Number(1e20).as_i128(), None);
+        assert_eq!(
+            Value::Number(MAX_SAFE_INTEGER).as_i128(),
+            Some(9_007_199_254_740_992)
+        );
+        assert_eq!(Value::Number(MAX_SAFE_INTEGER * 2.0).as_i128(), None);
+
+        let value: Value = "18446744073709551615".parse().unwrap();
+        assert_eq!(value, Value::Number(u64::MAX as f64));
+        assert_eq!(value.as_i128(), None);
+    }
+
+    #[test]
+    fn as_i128_non_integral() {
+        assert_eq!(Value::Number(1.5).as_i128(), None);
+        assert_eq!(Value::Number(f64::NAN).as_i128(), None);
+        assert_eq!(Value::String("1.5".to_string()).as_i128(), None);
+        assert_eq!(Value::Boolean(true).as_i128(), None);
+    }
+
+    #[test]
+    fn cow_string_borrowed() {
+        let value = Parser::from_str(r#""plain text""#).to_value().unwrap();
+
+        assert!(matches!(
+            value.cow_string(),
+            Some(Cow::Borrowed("plain text"))
+        ));
+    }
+
+    #[test]
+    fn string_borrows_from_source() {
+        let text = r#"["plain", "esc\taped"]"#;
+        let value = Parser::from_str(text).to_value().unwrap();
+
+        let ValueRef::Array(values) = &value else {
+            panic!("expected an array");
+        };
+        let [
+            ValueRef::String(Cow::Borrowed(plain)),
+            ValueRef::String(Cow::Owned(_)),
+        ] = values.as_slice()
+        else {
+            panic!("expected a borrowed and an owned string");
+        };
+        assert_eq!(plain.as_ptr(), text[2..].as_ptr());
+        assert_eq!(
+            value.to_value(),
+            Value::Array(vec![
+                Value::String("plain".to_string()),
+                Value::String("esc\taped".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn cow_string_owned() {
+        let value = Parser::from_str(r#""say \"hi\"\n""#).to_value().unwrap();
+
+        assert!(matches!(
+            value.cow_string(),
+            Some(Cow::Owned(x)) if x == "say \"hi\"\n"
+        ));
+        assert_eq!(ValueRef::Integer(1).cow_string(), None);
+    }
+
+    #[test]
+    fn references() {
+        let source = r#"{"a": [$x, y, {"b": x}], "c": {"d": [[z]]}, "e": "x", "f": x}"#;
+        let expected = HashSet::from_iter(vec!["x", "y", "z"]);
+
+        let value_ref = Parser::from_str(source).to_value().unwrap();
+        assert_eq!(value_ref.references(), expected);
+        assert_eq!(value_ref.to_value().references(), expected);
+
+        assert!(Value::Null.references().is_empty());
+        assert!(ValueRef::String(Cow::Borrowed("x")).references().is_empty());
+    }
+
+    #[test]
+    fn unresolved_references() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Reference("known".to_string())),
+            (
+                "b".into(),
+                Value::Array(vec![
+                    Value::Reference("missing".to_string()),
+                    Value::Reference("other_missing".to_string()),
+                    Value::Reference("missing".to_string()),
+                ]),
+            ),
+        ]));
+        let table = HashMap::from_iter(vec![("known".to_string(), Value::Integer(1))]);
+
+        assert_eq!(
+            value.unresolved_references(&table),
+            vec!["missing".to_string(), "other_missing".to_string()]
+        );
+        assert!(Value::Null.unresolved_references(&table).is_empty());
+    }
+
+    fn select_fixture() -> Value {
+        let item = |name: &str, size: i64| {
+            Value::Object(HashMap::from_iter(vec![
+                ("name".into(), Value::String(name.to_string())),
+                ("size".into(), Value::Integer(size)),
+            ]))
+        };
+
+        Value::Object(HashMap::from_iter(vec![
+            (
+                "items".into(),
+                Value::Array(vec![item("a", 1), item("b", 2), item("c", 3)]),
+            ),
+            (
+                "groups".into(),
+                Value::Object(HashMap::from_iter(vec![
+                    ("x".into(), item("x", 10)),
+                    ("y".into(), item("y", 20)),
+                ])),
+            ),
+        ]))
+    }
+
+    #[test]
+    fn select_wildcard() {
+        let value = select_fixture();
+
+        let mut sizes: Vec<_> = value
+            .select("groups.*.size")
+            .into_iter()
+            .filter_map(|v| match v {
+                Value::Integer(x) => Some(*x),
+                _ => None,
+            })
+            .collect();
+        sizes.sort();
+        assert_eq!(sizes, vec![10, 20]);
+        assert_eq!(value.select("items[*].size").len(), 3);
+        assert_eq!(value.select("items.*.name").len(), 3);
+    }
+
+    #[test]
+    fn select_index() {
+        let value = select_fixture();
+
+        assert_eq!(
+            value.select("items[1].name"),
+            vec![&Value::String("b".to_string())]
+        );
+        assert!(value.select("items[5].name").is_empty());
+        assert!(value.select("groups[0]").is_empty());
+        assert_eq!(value.select(""), vec![&value]);
+    }
+
+    #[test]
+    fn select_slice() {
+        let value = select_fixture();
+
+        assert_eq!(
+            value.select("items[0:2].name"),
+            vec![
+                &Value::String("a".to_string()),
+                &Value::String("b".to_string())
+            ]
+        );
+        assert_eq!(value.select("items[1:].name").len(), 2);
+        assert_eq!(value.select("items[:1].name").len(), 1);
+        assert_eq!(value.select("items[2:1]").len(), 0);
+    }
+
+    #[test]
+    fn select_invalid_query() {
+        let value = select_fixture();
+
+        assert!(value.select("items[a]").is_empty());
+        assert!(value.select("items[0").is_empty());
+        assert!(value.select("items..name").is_empty());
+        assert!(value.select("items]").is_empty());
+    }
+
+    #[test]
+    fn as_string_map() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            (
+                "content-type".into(),
+                Value::String("application/json".to_string()),
+            ),
+            ("accept".into(), Value::String("*/*".to_string())),
+        ]));
+
+        assert_eq!(
+            value.as_string_map(),
+            Some(HashMap::from_iter(vec![
+                ("content-type", "application/json"),
+                ("accept", "*/*")
+            ]))
+        );
+    }
+
+    #[test]
+    fn as_string_map_non_string() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::String("b".to_string())),
+            ("c".into(), Value::Integer(1)),
+        ]));
+
+        assert_eq!(value.as_string_map(), None);
+        assert_eq!(Value::Array(Vec::new()).as_string_map(), None);
+    }
+
+    #[test]
+    fn to_ndjson() {
+        let value = Value::Array(vec![
+            Value::Object(HashMap::from_iter(vec![("a".into(), Value::Integer(1))])),
+            Value::Object(HashMap::from_iter(vec![("b".into(), Value::Integer(2))])),
+        ]);
+
+        assert_eq!(value.to_ndjson(), Ok("{\"a\":1}\n{\"b\":2}\n".to_string()));
+        assert_eq!(Value::Null.to_ndjson(), Err(Error::NotAnArray));
+    }
+
+    #[test]
+    fn compare_with_primitives() {
+        assert!(Value::String("hello".to_string()) == "hello");
+        assert!(Value::String("hello".to_string()) != "world");
+        assert!(Value::Integer(42) == 42);
+        assert!(Value::Number(1.5) == 1.5);
+        assert!(Value::Boolean(true) == true);
+    }
+
+    #[test]
+    fn compare_with_primitives_type_mismatch() {
+        assert!(Value::Integer(42) != 42.0);
+        assert!(Value::Number(42.0) != 42);
+        assert!(Value::String("true".to_string()) != true);
+        assert!(Value::Reference("hello".to_string()) != "hello");
+        assert!(Value::Null != false);
+    }
+
+    #[test]
+    fn count_leaves_and_max_array_len() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Integer(1)),
+            (
+                "b".into(),
+                Value::Array(vec![
+                    Value::Null,
+                    Value::Array(vec![
+                        Value::Boolean(true),
+                        Value::Boolean(false),
+                        Value::Integer(2),
+                        Value::Integer(3),
+                    ]),
+                    Value::Object(HashMap::from_iter(vec![(
+                        "c".into(),
+                        Value::String("d".to_string()),
+                    )])),
+                ]),
+            ),
+            ("e".into(), Value::Array(Vec::new())),
+        ]));
+
+        assert_eq!(value.count_leaves(), 7);
+        assert_eq!(value.max_array_len(), 4);
+        assert_eq!(Value::Integer(1).count_leaves(), 1);
+        assert_eq!(Value::Integer(1).max_array_len(), 0);
+    }
+
+    #[test]
+    fn interpolate() {
+        let vars = HashMap::from_iter(vec![
+            ("host".to_string(), "localhost".to_string()),
+            ("port".to_string(), "8080".to_string()),
+        ]);
+        let mut value = Value::Object(HashMap::from_iter(vec![
+            ("url".into(), Value::String("${host}:${port}".to_string())),
+            (
+                "other".into(),
+                Value::Array(vec![
+                    Value::String("${user}@${host}".to_string()),
+                    Value::String("${host".to_string()),
+                    Value::Integer(1),
+                ]),
+            ),
+        ]));
+
+        value.interpolate(&vars, false).unwrap();
+
+        let expected = Value::Object(HashMap::from_iter(vec![
+            ("url".into(), Value::String("localhost:8080".to_string())),
+            (
+                "other".into(),
+                Value::Array(vec![
+                    Value::String("${user}@localhost".to_string()),
+                    Value::String("${host".to_string()),
+                    Value::Integer(1),
+                ]),
+            ),
+        ]));
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn interpolate_strict() {
+        let vars = HashMap::from_iter(vec![("host".to_string(), "localhost".to_string())]);
+
+        let mut value = Value::String("${host}:${port}".to_string());
+        assert_eq!(
+            value.interpolate(&vars, true),
+            Err(Error::UnknownVariable("port".to_string()))
+        );
+
+        let mut value = Value::String("${host}".to_string());
+        assert_eq!(value.interpolate(&vars, true), Ok(()));
+        assert_eq!(value, Value::String("localhost".to_string()));
+    }
+
+    #[test]
+    fn as_tagged() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            ("type".into(), Value::String("Circle".to_string())),
+            ("value".into(), Value::Number(1.5)),
+        ]));
+        assert_eq!(
+            value.as_tagged("type"),
+            Some(("Circle", &Value::Number(1.5)))
+        );
+        assert_eq!(value.as_tagged("kind"), None);
+
+        let value = Value::Object(HashMap::from_iter(vec![(
+            "type".into(),
+            Value::String("Empty".to_string()),
+        )]));
+        assert_eq!(value.as_tagged("type"), Some(("Empty", &Value::Null)));
+
+        let value = Value::Object(HashMap::from_iter(vec![
+            ("type".into(), Value::String("Point".to_string())),
+            ("x".into(), Value::Integer(1)),
+            ("y".into(), Value::Integer(2)),
+        ]));
+        assert_eq!(value.as_tagged("type"), None);
+    }
+
+    #[test]
+    fn as_tagged_non_object() {
+        assert_eq!(Value::String("type".to_string()).as_tagged("type"), None);
+        assert_eq!(Value::Array(Vec::new()).as_tagged("type"), None);
+    }
+
+    #[test]
+    fn with_capacity() {
+        match Value::object_with_capacity(64) {
+            Value::Object(hash_map) => {
+                assert!(hash_map.is_empty());
+                assert!(hash_map.capacity() >= 64);
+            }
+            other => panic!("expected an object, got {:?}", other),
+        }
+
+        match Value::array_with_capacity(64) {
+            Value::Array(values) => {
+                assert!(values.is_empty());
+                assert!(values.capacity() >= 64);
+            }
+            other => panic!("expected an array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sanitize_keys() {
+        let mut value = Value::Object(HashMap::from_iter(vec![
+            ("first name".into(), Value::String("a".to_string())),
+            (
+                "nested list".into(),
+                Value::Array(vec![Value::Object(HashMap::from_iter(vec![(
+                    "last name".into(),
+                    Value::String("b".to_string()),
+                )]))]),
+            ),
+        ]));
+
+        value.sanitize_keys(|key| key.replace(' ', "_")).unwrap();
+
+        let expected = Value::Object(HashMap::from_iter(vec![
+            ("first_name".into(), Value::String("a".to_string())),
+            (
+                "nested_list".into(),
+                Value::Array(vec![Value::Object(HashMap::from_iter(vec![(
+                    "last_name".into(),
+                    Value::String("b".to_string()),
+                )]))]),
+            ),
+        ]));
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn sanitize_keys_collision() {
+        let original = Value::Object(HashMap::from_iter(vec![
+            ("a b".into(), Value::Integer(1)),
+            ("a_b".into(), Value::Integer(2)),
+        ]));
+        let mut value = original.clone();
+
+        assert_eq!(
+            value.sanitize_keys(|key| key.replace(' ', "_")),
+            Err(Error::KeyCollision("a_b".to_string()))
+        );
+        assert_eq!(value, original);
+    }
+
+    #[test]
+    fn same_shape() {
+        let left = Value::Object(HashMap::from_iter(vec![
+            ("name".into(), Value::String("a".to_string())),
+            (
+                "tags".into(),
+                Value::Array(vec![Value::Integer(1), Value::Null]),
+            ),
+        ]));
+        let right = Value::Object(HashMap::from_iter(vec![
+            ("name".into(), Value::String("b".to_string())),
+            (
+                "tags".into(),
+                Value::Array(vec![Value::Integer(2), Value::Null]),
+            ),
+        ]));
+
+        assert!(left.same_shape(&right));
+        assert!(!left.same_shape(&Value::Null));
+        assert!(!Value::Integer(1).same_shape(&Value::Number(1.0)));
+    }
+
+    #[test]
+    fn same_shape_array_length() {
+        let left = Value::Array(vec![Value::Integer(1), Value::Integer(2)]);
+        let right = Value::Array(vec![Value::Integer(1)]);
+
+        assert!(!left.same_shape(&right));
+    }
+
+    #[test]
+    fn first_and_last() {
+        let value = Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(3),
+        ]);
+        assert_eq!(value.first(), Some(&Value::Integer(1)));
+        assert_eq!(value.last(), Some(&Value::Integer(3)));
+
+        let value = Value::Array(Vec::new());
+        assert_eq!(value.first(), None);
+        assert_eq!(value.last(), None);
+
+        let value = Value::String("abc".to_string());
+        assert_eq!(value.first(), None);
+        assert_eq!(value.last(), None);
+    }
+
+    #[test]
+    fn resolve_references_with_span() {
+        let source = "{\n  \"a\": known,\n  \"b\": [1, $missing]\n}";
+        let context = HashMap::from_iter(vec![("known".to_string(), Value::Integer(1))]);
+
+        let value = Parser::from_str(source).to_value().unwrap();
+        let error = value.resolve_references(&context).unwrap_err();
+
+        let span = Span::new(27, 35);
+        assert_eq!(
+            error,
+            ResolveError::Undefined {
+                name: "missing".to_string(),
+                span: Some(span),
+            }
+        );
+        assert_eq!(&source[span.as_range()], "$missing");
+        assert_eq!(LineIndex::new(source).line_col(27), (2, 11));
+    }
+
+    #[test]
+    fn resolve_references_found() {
+        let source = "[known, {\"a\": known}]";
+        let context = HashMap::from_iter(vec![("known".to_string(), Value::Boolean(true))]);
+
+        let value = Parser::from_str(source).to_value().unwrap();
+        let expected = Value::Array(vec![
+            Value::Boolean(true),
+            Value::Object(HashMap::from_iter(vec![("a".into(), Value::Boolean(true))])),
+        ]);
+        assert_eq!(value.resolve_references(&context), Ok(expected));
+
+        let error = ValueRef::Reference("missing", None)
+            .resolve_references(&context)
+            .unwrap_err();
+        assert!(matches!(error, ResolveError::Undefined { span: None, .. }));
+    }
+
+    #[test]
+    fn value_resolve_references() {
+        let context = HashMap::from_iter(vec![
+            (
+                "known".to_string(),
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::Reference("inner".to_string()),
+                ]),
+            ),
+            ("inner".to_string(), Value::Boolean(true)),
+        ]);
+        let known = Value::Array(vec![Value::Integer(1), Value::Boolean(true)]);
+
+        let top_level = Value::Reference("known".to_string());
+        assert_eq!(top_level.resolve_references(&context), Ok(known.clone()));
+
+        let in_array = Value::Array(vec![
+            Value::Null,
+            Value::Object(HashMap::from_iter(vec![(
+                "a".into(),
+                Value::Reference("known".to_string()),
+            )])),
+        ]);
+        let expected = Value::Array(vec![
+            Value::Null,
+            Value::Object(HashMap::from_iter(vec![("a".into(), known)])),
+        ]);
+        assert_eq!(in_array.resolve_references(&context), Ok(expected));
+
+        let undefined = Value::Array(vec![Value::Reference("missing".to_string())]);
+        assert_eq!(
+            undefined.resolve_references(&context),
+            Err(ResolveError::Undefined {
+                name: "missing".to_string(),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn resolve_references_cycle() {
+        let context = HashMap::from_iter(vec![
+            ("a".to_string(), Value::Reference("b".to_string())),
+            (
+                "b".to_string(),
+                Value::Array(vec![Value::Reference("a".to_string())]),
+            ),
+            ("c".to_string(), Value::Reference("a".to_string())),
+        ]);
+
+        let cycle = |names: &[&str]| {
+            Err(ResolveError::Cycle(
+                names.iter().map(|n| n.to_string()).collect(),
+            ))
+        };
+
+        assert_eq!(
+            Value::Reference("a".to_string()).resolve_references(&context),
+            cycle(&["a", "b", "a"])
+        );
+        // The path starts where the cycle does, not at the reference that led into it.
+        assert_eq!(
+            Value::Reference("c".to_string()).resolve_references(&context),
+            cycle(&["a", "b", "a"])
+        );
+        assert_eq!(
+            ValueRef::Reference("b", None).resolve_references(&context),
+            cycle(&["b", "a", "b"])
+        );
+    }
+
+    #[test]
+    fn resolve_references_chain() {
+        let context = HashMap::from_iter(vec![
+            ("a".to_string(), Value::Reference("b".to_string())),
+            ("b".to_string(), Value::Integer(42)),
+            (
+                "both".to_string(),
+                Value::Array(vec![
+                    Value::Reference("a".to_string()),
+                    Value::Reference("b".to_string()),
+                ]),
+            ),
+        ]);
+
+        assert_eq!(
+            Value::Reference("a".to_string()).resolve_references(&context),
+            Ok(Value::Integer(42))
+        );
+
+        let source = "[both, a]";
+        let value = Parser::from_str(source).to_value().unwrap();
+        let resolved = value.resolve_references(&context).unwrap();
+        assert!(resolved.references().is_empty());
+        assert_eq!(
+            resolved,
+            Value::Array(vec![
+                Value::Array(vec![Value::Integer(42), Value::Integer(42)]),
+                Value::Integer(42),
+            ])
+        );
+    }
+
+    #[test]
+    fn resolve_references_chain_to_undefined() {
+        let context = HashMap::from_iter(vec![
+            ("a".to_string(), Value::Reference("b".to_string())),
+            (
+                "b".to_string(),
+                Value::Array(vec![Value::Reference("missing".to_string())]),
+            ),
+        ]);
+
+        assert_eq!(
+            Value::Reference("a".to_string()).resolve_references(&context),
+            Err(ResolveError::Undefined {
+                name: "missing".to_string(),
+                span: None,
+            })
+        );
+    }
+
+    #[test]
+    fn resolve_references_self_cycle() {
+        let context = HashMap::from_iter(vec![(
+            "a".to_string(),
+            Value::Object(HashMap::from_iter(vec![(
+                "self".into(),
+                Value::Reference("a".to_string()),
+            )])),
+        )]);
+
+        assert_eq!(
+            Value::Reference("a".to_string()).resolve_references(&context),
+            Err(ResolveError::Cycle(vec!["a".to_string(), "a".to_string()]))
+        );
+    }
+
+    #[test]
+    fn try_resolve_partial() {
+        let mut value = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Reference("known".to_string())),
+            (
+                "b".into(),
+                Value::Array(vec![
+                    Value::Reference("later".to_string()),
+                    Value::Reference("known".to_string()),
+                ]),
+            ),
+        ]));
+        let context = HashMap::from_iter(vec![("known".to_string(), Value::Integer(1))]);
+
+        let unresolved = value.try_resolve_partial(&context);
+
+        assert_eq!(unresolved, HashSet::from_iter(vec!["later".to_string()]));
+        let expected = Value::Object(HashMap::from_iter(vec![
+            ("a".into(), Value::Integer(1)),
+            (
+                "b".into(),
+                Value::Array(vec![
+                    Value::Reference("later".to_string()),
+                    Value::Integer(1),
+                ]),
+            ),
+        ]));
+        assert_eq!(value, expected);
+
+        let context = HashMap::from_iter(vec![("later".to_string(), Value::Null)]);
+        assert!(value.try_resolve_partial(&context).is_empty());
+    }
+
+    #[test]
+    fn as_f64_lossy_large_integer() {
+        assert_eq!(
+            Value::Integer(i64::MAX).as_f64_lossy(),
+            Some(9.223372036854776e18)
+        );
+        assert_eq!(
+            Value::Integer((1 << 53) + 1).as_f64_lossy(),
+            Some((1u64 << 53) as f64)
+        );
+        assert_eq!(Value::Number(0.5).as_f64_lossy(), Some(0.5));
+        assert_eq!(Value::String("1".to_string()).as_f64_lossy(), None);
+    }
+
+    #[test]
+    fn as_i64_checked_fractional() {
+        assert_eq!(Value::Number(2.5).as_i64_checked(), None);
+        assert_eq!(Value::Number(2.0).as_i64_checked(), Some(2));
+        assert_eq!(Value::Number(1e19).as_i64_checked(), None);
+        assert_eq!(Value::Integer(i64::MAX).as_i64_checked(), Some(i64::MAX));
+    }
+
+    fn records() -> Value {
+        let record = |id: i64, name: &str| {
+            Value::Object(HashMap::from_iter(vec![
+                ("id".into(), Value::Integer(id)),
+                ("name".into(), Value::String(name.to_string())),
+                ("secret".into(), Value::String("hunter2".to_string())),
+                (
+                    "meta".into(),
+                    Value::Object(HashMap::from_iter(vec![
+                        ("secret".into(), Value::Boolean(true)),
+                        ("id".into(), Value::Integer(id * 10)),
+                    ])),
+                ),
+            ]))
+        };
+
+        Value::Array(vec![record(1, "a"), record(2, "b")])
+    }
+
+    #[test]
+    fn retain_keys() {
+        let mut value = records();
+        value.retain_keys(&["id", "name"]);
+
+        let record = |id: i64, name: &str| {
+            Value::Object(HashMap::from_iter(vec![
+                ("id".into(), Value::Integer(id)),
+                ("name".into(), Value::String(name.to_string())),
+            ]))
+        };
+        assert_eq!(value, Value::Array(vec![record(1, "a"), record(2, "b")]));
+    }
+
+    #[test]
+    fn remove_keys() {
+        let mut value = records();
+        value.remove_keys(&["secret"]);
+
+        let record = |id: i64, name: &str| {
+            Value::Object(HashMap::from_iter(vec![
+                ("id".into(), Value::Integer(id)),
+                ("name".into(), Value::String(name.to_string())),
+                (
+                    "meta".into(),
+                    Value::Object(HashMap::from_iter(vec![(
+                        "id".into(),
+                        Value::Integer(id * 10),
+                    )])),
+                ),
+            ]))
+        };
+        assert_eq!(value, Value::Array(vec![record(1, "a"), record(2, "b")]));
+    }
+
+    #[test]
+    fn path_exists() {
+        let value = Value::Object(HashMap::from_iter(vec![(
+            "server".into(),
+            Value::Object(HashMap::from_iter(vec![(
+                "ports".into(),
+                Value::Array(vec![Value::Integer(80), Value::Integer(443)]),
+            )])),
+        )]));
+
+        assert!(value.path_exists(""));
+        assert!(value.path_exists("/server"));
+        assert!(value.path_exists("/server/ports/1"));
+
+        assert!(!value.path_exists("/server/host"));
+        assert!(!value.path_exists("/server/ports/2"));
+        assert!(!value.path_exists("/server/ports/01"));
+        assert!(!value.path_exists("/server/ports/-"));
+    }
+
+    #[test]
+    fn pointer_traversal_and_escapes() {
+        let value: Value = r#"{"foo": [{"bar": 1}, 2], "a/b": 3, "m~n": 4, "": 5, "~1": 6}"#
+            .parse()
+            .unwrap();
+
+        assert_eq!(value.pointer(""), Some(&value));
+        assert_eq!(value.pointer("/foo/0/bar"), Some(&Value::Integer(1)));
+        assert_eq!(value.pointer("/foo/1"), Some(&Value::Integer(2)));
+        assert_eq!(value.pointer("/a~1b"), Some(&Value::Integer(3)));
+        assert_eq!(value.pointer("/m~0n"), Some(&Value::Integer(4)));
+        assert_eq!(value.pointer("/"), Some(&Value::Integer(5)));
+        assert_eq!(value.pointer("/~01"), Some(&Value::Integer(6)));
+
+        assert_eq!(value.pointer("/foo/2"), None);
+        assert_eq!(value.pointer("/foo/x"), None);
+        assert_eq!(value.pointer("/foo/0/baz"), None);
+        assert_eq!(value.pointer("/a~1b/0"), None);
+    }
+
+    #[test]
+    fn pointer_mut_edits_in_place() {
+        let mut value: Value = r#"{"foo": [{"bar": 1}], "a/b": 3}"#.parse().unwrap();
+
+        *value.pointer_mut("/foo/0/bar").unwrap() = Value::Boolean(true);
+        *value.pointer_mut("/a~1b").unwrap() = Value::Null;
+        assert!(value.pointer_mut("/foo/1").is_none());
+        assert!(value.pointer_mut("foo").is_none());
+
+        assert_eq!(value.pointer("/foo/0/bar"), Some(&Value::Boolean(true)));
+        assert_eq!(value.pointer("/a~1b"), Some(&Value::Null));
+
+        *value.pointer_mut("").unwrap() = Value::Integer(0);
+        assert_eq!(value, Value::Integer(0));
+    }
+
+    #[test]
+    fn path_exists_malformed_pointer() {
+        let value = Value::Object(HashMap::from_iter(vec![("server".into(), Value::Null)]));
+
+        assert!(!value.path_exists("server"));
+        assert!(!value.path_exists("/server~2"));
+        assert!(!value.path_exists("/server~"));
+    }
+
+    #[test]
+    fn to_scalar_string() {
+        assert_eq!(
+            Value::String("a b".to_string()).to_scalar_string(),
+            Some("a b".to_string())
+        );
+        assert_eq!(
+            Value::Integer(-3).to_scalar_string(),
+            Some("-3".to_string())
+        );
+        assert_eq!(
+            Value::Number(2.5).to_scalar_string(),
+            Some("2.5".to_string())
+        );
+        assert_eq!(
+            Value::Boolean(true).to_scalar_string(),
+            Some("true".to_string())
+        );
+        assert_eq!(Value::Null.to_scalar_string(), Some("null".to_string()));
+        assert_eq!(Value::Array(Vec::new()).to_scalar_string(), None);
+        assert_eq!(Value::Object(HashMap::new()).to_scalar_string(), None);
+    }
+
+    #[test]
+    fn partition_array() {
+        let value = Value::Array((1..=4).map(Value::Integer).collect());
+        let is_even = |v: &Value| matches!(v, Value::Integer(x) if x % 2 == 0);
+
+        assert_eq!(
+            value.partition_array(is_even),
+            Some((
+                Value::Array(vec![Value::Integer(2), Value::Integer(4)]),
+                Value::Array(vec![Value::Integer(1), Value::Integer(3)]),
+            ))
+        );
+        assert_eq!(Value::Integer(2).partition_array(is_even), None);
+    }
+
+    #[test]
+    fn as_value_ref() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            ("name".into(), Value::String("a".to_string())),
+            (
+                "items".into(),
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::Reference("other".to_string()),
+                ]),
+            ),
+        ]));
+
+        let ValueRef::Object(map) = value.as_value_ref() else {
+            panic!("expected an object");
+        };
+        let Some(ValueRef::String(name)) = map.get("name") else {
+            panic!("expected a string");
+        };
+        let Some(Value::String(owned)) = value.pointer("/name") else {
+            panic!("expected a string");
+        };
+        assert_eq!(*name, "a");
+        assert_eq!(name.as_ptr(), owned.as_ptr());
+        assert_eq!(
+            map.get("items"),
+            Some(&ValueRef::Array(vec![
+                ValueRef::Integer(1),
+                ValueRef::Reference("other", None)
+            ]))
+        );
+        assert_eq!(value.as_value_ref().to_value(), value);
+    }
+
+    #[test]
+    fn is_scalar_and_is_container() {
+        let cases = vec![
+            (Value::String("a".to_string()), true),
+            (Value::Integer(1), true),
+            (Value::Number(1.5), true),
+            (Value::Boolean(true), true),
+            (Value::Reference("a".to_string()), true),
+            (Value::Null, true),
+            (Value::Array(Vec::new()), false),
+            (Value::Object(HashMap::new()), false),
+        ];
+
+        for (value, scalar) in cases {
+            assert_eq!(value.is_scalar(), scalar, "{:?}", value);
+            assert_eq!(value.is_container(), !scalar, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn array_dedup() {
+        let mut value = Value::Array(vec![
+            Value::Integer(1),
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::String("a".to_string()),
+            Value::String("a".to_string()),
+        ]);
+        value.array_dedup();
+
+        assert_eq!(
+            value,
+            Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(2),
+                Value::String("a".to_string()),
+            ])
+        );
+
+        let mut value = Value::String("a".to_string());
+        value.array_dedup();
+        assert_eq!(value, "a");
+    }
+
+    #[test]
+    fn get_as() {
+        let value = Value::Object(HashMap::from_iter(vec![(
+            "server".into(),
+            Value::Object(HashMap::from_iter(vec![
+                ("port".into(), Value::Integer(8080)),
+                ("host".into(), Value::String("localhost".to_string())),
+            ])),
+        )]));
+
+        assert_eq!(value.get_as::<i64>("/server/port"), Ok(8080));
+        assert_eq!(
+            value.get_as::<String>("/server/host"),
+            Ok("localhost".to_string())
+        );
+        assert_eq!(
+            value.get_as::<i64>("/server/timeout"),
+            Err(Error::MissingPath("/server/timeout".to_string()))
+        );
+        assert_eq!(
+            value.get_as::<i64>("/server/host"),
+            Err(Error::UnexpectedType("/server/host".to_string()))
+        );
+    }
+
+    #[test]
+    fn raw_number_accessors() {
+        let raw = |text: &str| Value::RawNumber(text.to_string());
+
+        assert!(raw("1").is_number());
+        assert!(raw("1.50").is_number());
+        assert_eq!(raw("42").as_i64(), Some(42));
+        assert_eq!(raw("1.50").as_i64(), None);
+        assert_eq!(raw("1.50").as_f64(), Some(1.5));
+        assert_eq!(raw("2.0").as_i64_checked(), Some(2));
+        assert_eq!(raw("0xFF").as_i64(), Some(255));
+        assert_eq!(raw("+1_000").as_i64(), Some(1000));
+        assert_eq!(raw("1e2").as_i128(), Some(100));
+        assert_eq!(
+            raw("18446744073709551615").as_i128(),
+            Some(i128::from(u64::MAX))
+        );
+
+        assert_eq!(raw("7"), 7);
+        assert_ne!(raw("7"), 7.0);
+        assert_eq!(raw("7.0"), 7.0);
+        assert_ne!(raw("7.5"), 7);
+
+        let config = parser::ParserConfig {
+            preserve_number_text: true,
+            ..Default::default()
+        };
+        let value = Parser::from_str(r#"{"port": 8080, "ratio": 0.250}"#)
+            .with_config(config)
+            .to_value()
+            .unwrap()
+            .to_value();
+        assert_eq!(value.get_as::<i64>("/port"), Ok(8080));
+        assert_eq!(value.get_as::<f64>("/ratio"), Ok(0.25));
+    }
+
+    #[test]
+    fn flatten_one() {
+        let mut value = Value::Array(vec![
+            Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+            Value::Array(vec![Value::Array(vec![Value::Integer(3)])]),
+        ]);
+        value.flatten_one();
+
+        assert_eq!(
+            value,
+            Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(2),
+                Value::Array(vec![Value::Integer(3)]),
+            ])
+        );
+
+        let flat = Value::Array(vec![Value::Integer(1), Value::Integer(2)]);
+        let mut value = flat.clone();
+        value.flatten_one();
+        assert_eq!(value, flat);
+    }
+
+    #[test]
+    fn object_equality_ignores_order() {
+        let pairs: Vec<(Key, Value)> = vec![
+            ("a".into(), Value::Integer(1)),
+            (
+                "b".into(),
+                Value::Object(HashMap::from_iter(vec![
+                    ("c".into(), Value::Reference("c".to_string())),
+                    ("d".into(), Value::Null),
+                ])),
+            ),
+        ];
+        let forward = Value::Object(pairs.iter().cloned().collect());
+        let backward = Value::Object(pairs.into_iter().rev().collect());
+
+        assert_eq!(forward, backward);
+        assert!(forward.eq_nan_aware(&backward));
+        assert_ne!(
+            Value::Reference("c".to_string()),
+            Value::String("c".to_string())
+        );
+    }
+
+    #[test]
+    fn float_equality() {
+        assert_eq!(Value::Number(0.0), Value::Number(-0.0));
+        assert_ne!(Value::Number(1.0), Value::Integer(1));
+        assert_ne!(Value::Number(f64::NAN), Value::Number(f64::NAN));
+
+        let value = Value::Array(vec![Value::Object(HashMap::from_iter(vec![(
+            "x".into(),
+            Value::Number(f64::NAN),
+        )]))]);
+        assert_ne!(value, value.clone());
+        assert!(value.eq_nan_aware(&value.clone()));
+        assert!(!value.eq_nan_aware(&Value::Array(vec![Value::Number(f64::NAN)])));
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let string = Value::String("a".to_string());
+        let integer = Value::Integer(2);
+        let number = Value::Number(1.5);
+        let boolean = Value::Boolean(true);
+        let array = Value::Array(vec![Value::Null]);
+        let object = Value::Object(HashMap::from_iter(vec![("a".into(), Value::Null)]));
+
+        assert_eq!(string.as_str(), Some("a"));
+        assert_eq!(integer.as_i64(), Some(2));
+        assert_eq!(integer.as_f64(), Some(2.0));
+        assert_eq!(number.as_f64(), Some(1.5));
+        assert_eq!(boolean.as_bool(), Some(true));
+        assert_eq!(array.as_array(), Some(&vec![Value::Null]));
+        assert_eq!(object.as_object().map(HashMap::len), Some(1));
+
+        assert_eq!(integer.as_str(), None);
+        assert_eq!(number.as_i64(), None);
+        assert_eq!(string.as_f64(), None);
+        assert_eq!(Value::Null.as_bool(), None);
+        assert_eq!(object.as_array(), None);
+        assert_eq!(array.as_object(), None);
+    }
+
+    #[test]
+    fn type_predicates() {
+        let values = [
+            Value::String("a".to_string()),
+            Value::Integer(1),
+            Value::Number(1.0),
+            Value::Boolean(false),
+            Value::Array(Vec::new()),
+            Value::Object(HashMap::new()),
+            Value::Reference("a".to_string()),
+            Value::Null,
+        ];
+        let predicates: [fn(&Value) -> bool; 8] = [
+            Value::is_string,
+            Value::is_integer,
+            Value::is_number,
+            Value::is_bool,
+            Value::is_array,
+            Value::is_object,
+            Value::is_reference,
+            Value::is_null,
+        ];
+
+        for (i, value) in values.iter().enumerate() {
+            for (j, predicate) in predicates.iter().enumerate() {
+                assert_eq!(predicate(value), i == j, "{:?}", value);
+            }
+        }
+    }
+
+    #[test]
+    fn from_primitives() {
+        let value: Value = 1i64.into();
+        assert_eq!(value, Value::Integer(1));
+        let value: Value = 1.5.into();
+        assert_eq!(value, Value::Number(1.5));
+        let value: Value = true.into();
+        assert_eq!(value, Value::Boolean(true));
+        let value: Value = "a".into();
+        assert_eq!(value, Value::String("a".to_string()));
+        let value: Value = "b".to_string().into();
+        assert_eq!(value, Value::String("b".to_string()));
+        let value: Value = vec![Value::Null].into();
+        assert_eq!(value, Value::Array(vec![Value::Null]));
+        let value: Value = HashMap::from_iter(vec![("a".into(), Value::Null)]).into();
+        assert_eq!(
+            value,
+            Value::Object(HashMap::from_iter(vec![("a".into(), Value::Null)]))
+        );
+
+        let value: Value = Some(2i64).into();
+        assert_eq!(value, Value::Integer(2));
+        let value: Value = None::<&str>.into();
+        assert_eq!(value, Value::Null);
+    }
+
+    #[test]
+    fn index_by_key_and_position() {
+        let value: Value = r#"{"users": [{"name": "ada", "email": null}]}"#.parse().unwrap();
+
+        assert_eq!(value["users"][0]["name"], "ada");
+        assert_eq!(value["users"][1]["name"], Value::Null);
+        assert_eq!(value["groups"][0], Value::Null);
+        assert_eq!(value[0], Value::Null);
+
+        assert_eq!(value["users"][0].get("email"), Some(&Value::Null));
+        assert_eq!(value["users"][0].get("phone"), None);
+        assert_eq!(
+            value["users"].get_index(0).and_then(|u| u.get("name")),
+            Some(&Value::String("ada".to_string()))
+        );
+        assert_eq!(value["users"].get_index(1), None);
+        assert_eq!(value.get_index(0), None);
+    }
+
+    #[test]
+    fn scalar_inspectors() {
+        let value = Value::String("a".to_string());
+
+        assert_eq!(value.object_keys(), None);
+        assert_eq!(value.array_len(), None);
     }
 }
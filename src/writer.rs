@@ -0,0 +1,408 @@
+//! Serializing values back to JSON text.
+//!
+//! The [`Display`](fmt::Display) implementations of [`Value`] and [`ValueRef`] write compact
+//! JSON. References are written as their bare name, so the output can be read back by the
+//! parser. A raw number is written exactly as it was in the source. Use a [`Writer`] for
+//! indented output.
+
+use std::fmt::{self, Write};
+
+use crate::value::{Error, Value, ValueRef};
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(x) => write_string(f, x),
+            Value::Integer(x) => write!(f, "{}", x),
+            Value::Number(x) => write_number(f, *x),
+            Value::Boolean(x) => write!(f, "{}", x),
+            Value::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(hash_map) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in hash_map.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+            Value::Reference(x) => f.write_str(x),
+            Value::Null => f.write_str("null"),
+            Value::RawNumber(x) => f.write_str(x),
+        }
+    }
+}
+
+impl fmt::Display for ValueRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueRef::String(x) => write_string(f, x),
+            ValueRef::Integer(x) => write!(f, "{}", x),
+            ValueRef::Number(x) => write_number(f, *x),
+            ValueRef::Boolean(x) => write!(f, "{}", x),
+            ValueRef::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            ValueRef::Object(hash_map) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in hash_map.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+            ValueRef::Reference(x, _) => f.write_str(x),
+            ValueRef::Null => f.write_str("null"),
+            ValueRef::RawNumber(x) => f.write_str(x),
+        }
+    }
+}
+
+/// Writes values as indented JSON, with every array element and object entry on its own line.
+#[derive(Debug, Clone)]
+pub struct Writer {
+    indent: usize,
+    compact_arrays: bool,
+}
+
+impl Writer {
+    pub fn new(indent: usize) -> Self {
+        Writer {
+            indent,
+            compact_arrays: false,
+        }
+    }
+
+    /// Keep arrays that only contain scalars on a single line, like `[1, 2, 3]`.
+    pub fn with_compact_arrays(mut self) -> Self {
+        self.compact_arrays = true;
+        self
+    }
+
+    pub fn write(&self, value: &Value) -> String {
+        let mut out = String::new();
+        self.write_value(&mut out, value, 0)
+            .expect("writing to a String does not fail");
+        out
+    }
+
+    fn write_value<W: Write>(&self, f: &mut W, value: &Value, depth: usize) -> fmt::Result {
+        match value {
+            Value::Array(values) if values.is_empty() => f.write_str("[]"),
+            Value::Object(hash_map) if hash_map.is_empty() => f.write_str("{}"),
+            Value::Array(values)
+                if self.compact_arrays
+                    && !values
+                        .iter()
+                        .any(|v| matches!(v, Value::Array(_) | Value::Object(_))) =>
+            {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            Value::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    self.write_newline(f, depth + 1)?;
+                    self.write_value(f, value, depth + 1)?;
+                }
+                self.write_newline(f, depth)?;
+                f.write_char(']')
+            }
+            Value::Object(hash_map) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in hash_map.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    self.write_newline(f, depth + 1)?;
+                    write_string(f, key)?;
+                    f.write_str(": ")?;
+                    self.write_value(f, value, depth + 1)?;
+                }
+                self.write_newline(f, depth)?;
+                f.write_char('}')
+            }
+            scalar => write!(f, "{}", scalar),
+        }
+    }
+
+    fn write_newline<W: Write>(&self, f: &mut W, depth: usize) -> fmt::Result {
+        f.write_char('\n')?;
+        for _ in 0..depth * self.indent {
+            f.write_char(' ')?;
+        }
+        Ok(())
+    }
+}
+
+/// How records are delimited by the [`NdjsonWriter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordSeparator {
+    /// Every record ends with `\n`, as in NDJSON and JSON Lines.
+    LineFeed,
+    /// Every record ends with `\r\n`.
+    CarriageReturnLineFeed,
+    /// Every record starts with `\x1e` and ends with `\n`, as in JSON Text Sequences (RFC 7464).
+    RecordSeparator,
+}
+
+/// Writes every element of an array as a compact JSON record.
+#[derive(Debug, Clone)]
+pub struct NdjsonWriter {
+    separator: RecordSeparator,
+}
+
+impl NdjsonWriter {
+    pub fn new() -> Self {
+        NdjsonWriter {
+            separator: RecordSeparator::LineFeed,
+        }
+    }
+
+    pub fn with_separator(mut self, separator: RecordSeparator) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    pub fn write(&self, value: &Value) -> Result<String, Error> {
+        let Value::Array(values) = value else {
+            return Err(Error::NotAnArray);
+        };
+
+        let (prefix, suffix) = match self.separator {
+            RecordSeparator::LineFeed => ("", "\n"),
+            RecordSeparator::CarriageReturnLineFeed => ("", "\r\n"),
+            RecordSeparator::RecordSeparator => ("\x1e", "\n"),
+        };
+        Ok(values
+            .iter()
+            .map(|v| format!("{}{}{}", prefix, v, suffix))
+            .collect())
+    }
+}
+
+impl Default for NdjsonWriter {
+    fn default() -> Self {
+        NdjsonWriter::new()
+    }
+}
+
+impl Value {
+    /// Compact JSON wrapped in single quotes, safe to paste as one argument in a POSIX shell.
+    ///
+    /// Embedded single quotes are written as `'\''`, which closes the quoted string, adds an
+    /// escaped quote and opens a new quoted string.
+    pub fn escape_for_shell(&self) -> String {
+        format!("'{}'", self.to_string().replace('\'', r"'\''"))
+    }
+}
+
+/// Writes a float so it reads back as a float, JSON has no representation for `NaN` and the
+/// infinities so these are written as `null`.
+pub(crate) fn write_number<W: Write>(f: &mut W, x: f64) -> fmt::Result {
+    if x.is_finite() {
+        write!(f, "{:?}", x)
+    } else {
+        f.write_str("null")
+    }
+}
+
+pub(crate) fn write_string<W: Write>(f: &mut W, x: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for ch in x.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\u{c}' => f.write_str("\\f")?,
+            ch if ch.is_control() => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::parser::{Parser, ParserConfig};
+
+    #[test]
+    fn raw_number_round_trip() {
+        let config = ParserConfig {
+            preserve_number_text: true,
+            ..Default::default()
+        };
+        let text = "[123.4500,1e10,-0.0,12345678901234567890]";
+
+        let value_ref = Parser::from_str(text)
+            .with_config(config.clone())
+            .to_value()
+            .unwrap();
+        assert_eq!(value_ref.to_string(), text);
+        let value = value_ref.to_value();
+        assert_eq!(value.to_string(), text);
+        assert_eq!(value[0], Value::RawNumber("123.4500".to_string()));
+        assert_eq!(value[0].as_f64_lossy(), Some(123.45));
+
+        assert_eq!(
+            Parser::from_str("[007]").with_config(config).to_value(),
+            Err(crate::parser::Error::with_span(
+                crate::parser::ErrorKind::InvalidNumber,
+                crate::lexer::Span::new(1, 4)
+            ))
+        );
+    }
+
+    #[test]
+    fn display_scalars() {
+        assert_eq!(
+            Value::String("a\"b\n".to_string()).to_string(),
+            r#""a\"b\n""#
+        );
+        assert_eq!(Value::Integer(-12).to_string(), "-12");
+        assert_eq!(Value::Number(1.0).to_string(), "1.0");
+        assert_eq!(Value::Number(f64::NAN).to_string(), "null");
+        assert_eq!(Value::Boolean(false).to_string(), "false");
+        assert_eq!(Value::Reference("my_ref".to_string()).to_string(), "my_ref");
+        assert_eq!(Value::Null.to_string(), "null");
+    }
+
+    #[test]
+    fn escape_for_shell() {
+        let value = Value::Array(vec![Value::String("it's".to_string()), Value::Integer(1)]);
+
+        assert_eq!(value.escape_for_shell(), r#"'["it'\''s",1]'"#);
+        assert_eq!(Value::Null.escape_for_shell(), "'null'");
+    }
+
+    #[test]
+    fn writer_pretty() {
+        let value = Value::Object(HashMap::from_iter(vec![(
+            "a".into(),
+            Value::Array(vec![Value::Integer(1), Value::Array(Vec::new())]),
+        )]));
+
+        assert_eq!(
+            Writer::new(2).write(&value),
+            "{\n  \"a\": [\n    1,\n    []\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn writer_compact_arrays() {
+        let value = Value::Object(HashMap::from_iter(vec![
+            (
+                "points".into(),
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::Integer(2),
+                    Value::Integer(3),
+                ]),
+            ),
+            (
+                "items".into(),
+                Value::Array(vec![Value::Object(HashMap::from_iter(vec![(
+                    "b".into(),
+                    Value::Boolean(true),
+                )]))]),
+            ),
+        ]));
+        let text = Writer::new(2).with_compact_arrays().write(&value);
+
+        assert!(text.contains("\"points\": [1, 2, 3]"));
+        assert!(text.contains("\"items\": [\n    {\n      \"b\": true\n    }\n  ]"));
+    }
+
+    #[test]
+    fn ndjson_separators() {
+        let value = Value::Array(vec![Value::Integer(1), Value::Array(vec![Value::Null])]);
+
+        assert_eq!(
+            NdjsonWriter::new().write(&value),
+            Ok("1\n[null]\n".to_string())
+        );
+        assert_eq!(
+            NdjsonWriter::new()
+                .with_separator(RecordSeparator::CarriageReturnLineFeed)
+                .write(&value),
+            Ok("1\r\n[null]\r\n".to_string())
+        );
+        assert_eq!(
+            NdjsonWriter::new()
+                .with_separator(RecordSeparator::RecordSeparator)
+                .write(&value),
+            Ok("\x1e1\n\x1e[null]\n".to_string())
+        );
+        assert_eq!(
+            NdjsonWriter::new().write(&Value::Null),
+            Err(Error::NotAnArray)
+        );
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let text = r#"{"name": "outliner", "size": [1, -2.5, 3e2], "ok": true, "none": null, "ref": my_ref, "nested": {"empty": []}}"#;
+        let value = Parser::from_str(text).to_value().unwrap();
+
+        let rendered = value.to_string();
+        let reparsed = Parser::from_str(&rendered).to_value().unwrap();
+        // The reference spans point into different texts, so compare the owned values.
+        let owned = value.to_value();
+        assert_eq!(reparsed.to_value(), owned);
+
+        assert_eq!(
+            Parser::from_str(&owned.to_string())
+                .to_value()
+                .unwrap()
+                .to_value(),
+            owned
+        );
+    }
+
+    #[test]
+    fn display_array() {
+        let value = Value::Array(vec![
+            Value::Integer(1),
+            Value::Array(Vec::new()),
+            Value::Object(Default::default()),
+        ]);
+
+        assert_eq!(value.to_string(), "[1,[],{}]");
+    }
+}